@@ -0,0 +1,112 @@
+use object::Value;
+use scanner::Token;
+
+// Operands follow their opcode in the code stream: constant-pool indices
+// and jump offsets are two bytes (big-endian), slot and argument counts one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+	Constant,
+	Nil,
+	True,
+	False,
+	Pop,
+	GetLocal,
+	SetLocal,
+	GetGlobal,
+	DefineGlobal,
+	SetGlobal,
+	GetUpvalue,
+	SetUpvalue,
+	GetProperty,
+	SetProperty,
+	GetSuper,
+	Equal,
+	Greater,
+	Less,
+	Add,
+	Subtract,
+	Multiply,
+	Divide,
+	Not,
+	Negate,
+	Print,
+	Jump,
+	JumpIfFalse,
+	Loop,
+	Call,
+	Closure,
+	CloseUpvalue,
+	Return,
+	Class,
+	Inherit,
+	Method,
+}
+
+const OPCODES: [OpCode; 35] = [
+	OpCode::Constant, OpCode::Nil, OpCode::True, OpCode::False, OpCode::Pop,
+	OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal, OpCode::DefineGlobal, OpCode::SetGlobal,
+	OpCode::GetUpvalue, OpCode::SetUpvalue, OpCode::GetProperty, OpCode::SetProperty, OpCode::GetSuper,
+	OpCode::Equal, OpCode::Greater, OpCode::Less, OpCode::Add, OpCode::Subtract,
+	OpCode::Multiply, OpCode::Divide, OpCode::Not, OpCode::Negate, OpCode::Print,
+	OpCode::Jump, OpCode::JumpIfFalse, OpCode::Loop, OpCode::Call, OpCode::Closure,
+	OpCode::CloseUpvalue, OpCode::Return, OpCode::Class, OpCode::Inherit, OpCode::Method,
+];
+
+impl OpCode {
+	pub fn from_byte(byte: u8) -> Option<OpCode> {
+		OPCODES.get(byte as usize).cloned()
+	}
+}
+
+pub struct Chunk {
+	pub code: Vec<u8>,
+	pub constants: Vec<Value>,
+	// run-length line table: (first code offset, source token) for each
+	// run of bytes compiled from the same token
+	locations: Vec<(usize, Token)>,
+}
+
+impl Chunk {
+	pub fn new() -> Self {
+		Chunk { code: vec![], constants: vec![], locations: vec![] }
+	}
+
+	pub fn write(&mut self, byte: u8, token: &Token) {
+		let same_token = match self.locations.last() {
+			Some((_, last)) => last == token,
+			None => false,
+		};
+		if !same_token {
+			self.locations.push((self.code.len(), token.clone()));
+		}
+		self.code.push(byte);
+	}
+
+	pub fn add_constant(&mut self, value: Value) -> usize {
+		self.constants.push(value);
+		self.constants.len() - 1
+	}
+
+	pub fn read_short(&self, offset: usize) -> u16 {
+		(u16::from(self.code[offset]) << 8) | u16::from(self.code[offset + 1])
+	}
+
+	// the source token the byte at `offset` was compiled from
+	pub fn token(&self, offset: usize) -> &Token {
+		let run = match self.locations.binary_search_by(|&(start, _)| start.cmp(&offset)) {
+			Ok(run) => run,
+			Err(next_run) => next_run - 1,
+		};
+		&self.locations[run].1
+	}
+
+	pub fn line(&self, offset: usize) -> usize {
+		self.token(offset).line()
+	}
+}
+
+impl Default for Chunk {
+	fn default() -> Self {
+		Chunk::new()
+	}
+}
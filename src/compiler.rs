@@ -0,0 +1,570 @@
+use chunk::{Chunk, OpCode};
+use error::LoxError;
+use heap::{Heap, ObjRef};
+use object::{Function, Obj, Value};
+use parse::{Expression, FunctionDecl, Statement};
+use scanner::{Token, TokenType};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// slot and upvalue operands are a single byte
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+	Script,
+	Function,
+	Initializer,
+	Method,
+}
+
+struct Local {
+	name: String,
+	// None until the variable's initializer has been compiled
+	depth: Option<usize>,
+	is_captured: bool,
+}
+
+struct UpvalueRef {
+	index: u8,
+	// whether it captures a local of the enclosing function, rather than
+	// one of the enclosing function's own upvalues
+	is_local: bool,
+}
+
+// everything being built for one function body; nested function
+// declarations push a new one
+struct FunctionState {
+	function_type: FunctionType,
+	name: String,
+	arity: usize,
+	chunk: Chunk,
+	locals: Vec<Local>,
+	upvalues: Vec<UpvalueRef>,
+	scope_depth: usize,
+	// constant-pool slots already holding each identifier's name
+	identifiers: HashMap<String, u16>,
+}
+
+impl FunctionState {
+	fn new(function_type: FunctionType, name: String) -> Self {
+		// slot zero holds the function being called, or the receiver in methods
+		let mut slot_zero = "";
+		if function_type == FunctionType::Method || function_type == FunctionType::Initializer {
+			slot_zero = "this";
+		}
+		FunctionState {
+			function_type,
+			name,
+			arity: 0,
+			chunk: Chunk::new(),
+			locals: vec![Local { name: slot_zero.to_string(), depth: Some(0), is_captured: false }],
+			upvalues: vec![],
+			scope_depth: 0,
+			identifiers: HashMap::new(),
+		}
+	}
+}
+
+// Compiles a resolved program into bytecode for the VM in a single pass over
+// the tree. Static errors have already been reported by the parser and
+// resolver, so the only errors left are the bytecode format's limits.
+pub struct Compiler<'h> {
+	heap: &'h mut Heap,
+	states: Vec<FunctionState>,
+	// the token the next instruction is attributed to in the line table;
+	// runtime errors are reported at it
+	token: Token,
+}
+
+impl<'h> Compiler<'h> {
+	pub fn new(heap: &'h mut Heap) -> Self {
+		Compiler {
+			heap,
+			states: vec![FunctionState::new(FunctionType::Script, String::new())],
+			token: Token::new(TokenType::Eof, "", "", 1, 1),
+		}
+	}
+
+	// returns the top-level script as a function object
+	pub fn compile(mut self, program: &[Statement]) -> Result<ObjRef, LoxError> {
+		for stmt in program {
+			self.statement(stmt)?;
+		}
+		self.emit_return();
+		let state = self.states.pop().expect("script state");
+		let function = Function { name: state.name, arity: 0, upvalue_count: 0, chunk: Rc::new(state.chunk) };
+		Ok(self.heap.alloc(Obj::Function(function)))
+	}
+
+	fn current(&mut self) -> &mut FunctionState {
+		self.states.last_mut().expect("no function being compiled")
+	}
+
+	fn statement(&mut self, stmt: &Statement) -> Result<(), LoxError> {
+		match *stmt {
+			Statement::ExprStmt(ref e) => {
+				self.expression(e)?;
+				self.emit_op(OpCode::Pop);
+			},
+			Statement::PrintStmt(ref keyword, ref e) => {
+				self.expression(e)?;
+				self.set_token(keyword);
+				self.emit_op(OpCode::Print);
+			},
+			Statement::VarDecl(ref var_name, ref initializer) => {
+				self.set_token(var_name);
+				let global = self.declare_variable(var_name)?;
+				self.expression(initializer)?;
+				self.set_token(var_name);
+				self.define_variable(global);
+			},
+			Statement::Block(ref statements) => {
+				self.begin_scope();
+				for stmt in statements {
+					self.statement(stmt)?;
+				}
+				self.end_scope();
+			},
+			Statement::If(ref condition, ref then_branch, ref else_branch) => {
+				self.expression(condition)?;
+				let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit_op(OpCode::Pop);
+				self.statement(then_branch)?;
+				let else_jump = self.emit_jump(OpCode::Jump);
+				self.patch_jump(then_jump)?;
+				self.emit_op(OpCode::Pop);
+				if let Some(ref else_branch) = *else_branch {
+					self.statement(else_branch)?;
+				}
+				self.patch_jump(else_jump)?;
+			},
+			Statement::While(ref condition, ref body) => {
+				let loop_start = self.current().chunk.code.len();
+				self.expression(condition)?;
+				let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+				self.emit_op(OpCode::Pop);
+				self.statement(body)?;
+				self.emit_loop(loop_start)?;
+				self.patch_jump(exit_jump)?;
+				self.emit_op(OpCode::Pop);
+			},
+			Statement::Function(ref declaration) => {
+				self.set_token(&declaration.name);
+				let global = self.declare_variable(&declaration.name)?;
+				// initialized straight away so the body can call itself
+				self.mark_initialized();
+				self.function(declaration, FunctionType::Function)?;
+				self.set_token(&declaration.name);
+				self.define_variable(global);
+			},
+			Statement::Return(ref keyword, ref e) => {
+				self.set_token(keyword);
+				if self.current().function_type == FunctionType::Initializer {
+					self.emit_op(OpCode::GetLocal);
+					self.emit_byte(0);
+				} else {
+					self.expression(e)?;
+				}
+				self.set_token(keyword);
+				self.emit_op(OpCode::Return);
+			},
+			Statement::Class(ref class_name, ref superclass, ref methods) => {
+				self.class_declaration(class_name, superclass, methods)?;
+			},
+		}
+		Ok(())
+	}
+
+	fn class_declaration(&mut self, class_name: &Token, superclass: &Option<Expression>, methods: &[Rc<FunctionDecl>]) -> Result<(), LoxError> {
+		self.set_token(class_name);
+		let name_constant = self.identifier_constant(&class_name.lexeme())?;
+		let global = self.declare_variable(class_name)?;
+		self.emit_op(OpCode::Class);
+		self.emit_short(name_constant);
+		self.define_variable(global);
+
+		// the superclass lives on in a local named "super" that methods capture
+		if let Some(Expression::Variable(ref superclass_name, _)) = *superclass {
+			self.set_token(superclass_name);
+			self.named_variable(&superclass_name.lexeme(), false)?;
+			self.begin_scope();
+			self.add_local("super")?;
+			self.mark_initialized();
+			self.named_variable(&class_name.lexeme(), false)?;
+			self.emit_op(OpCode::Inherit);
+		}
+
+		self.set_token(class_name);
+		self.named_variable(&class_name.lexeme(), false)?;
+		for method in methods {
+			self.set_token(&method.name);
+			let method_constant = self.identifier_constant(&method.name.lexeme())?;
+			let mut function_type = FunctionType::Method;
+			if method.name.lexeme() == "init" {
+				function_type = FunctionType::Initializer;
+			}
+			self.function(method, function_type)?;
+			self.set_token(&method.name);
+			self.emit_op(OpCode::Method);
+			self.emit_short(method_constant);
+		}
+		self.emit_op(OpCode::Pop);
+
+		if superclass.is_some() {
+			self.end_scope();
+		}
+		Ok(())
+	}
+
+	// compiles the function body into its own chunk, then emits the code
+	// that wraps it in a closure at runtime
+	fn function(&mut self, declaration: &FunctionDecl, function_type: FunctionType) -> Result<(), LoxError> {
+		self.states.push(FunctionState::new(function_type, declaration.name.lexeme()));
+		self.begin_scope();
+		self.current().arity = declaration.params.len();
+		for param in &declaration.params {
+			self.set_token(param);
+			self.add_local(&param.lexeme())?;
+			self.mark_initialized();
+		}
+		for stmt in &declaration.body {
+			self.statement(stmt)?;
+		}
+		self.emit_return();
+
+		let state = self.states.pop().expect("function state");
+		let function = Function {
+			name: state.name,
+			arity: state.arity,
+			upvalue_count: state.upvalues.len(),
+			chunk: Rc::new(state.chunk),
+		};
+		let function_ref = self.heap.alloc(Obj::Function(function));
+
+		self.set_token(&declaration.name);
+		let constant = self.make_constant(Value::Obj(function_ref))?;
+		self.emit_op(OpCode::Closure);
+		self.emit_short(constant);
+		for upvalue in &state.upvalues {
+			self.emit_byte(upvalue.is_local as u8);
+			self.emit_byte(upvalue.index);
+		}
+		Ok(())
+	}
+
+	fn expression(&mut self, expr: &Expression) -> Result<(), LoxError> {
+		match *expr {
+			Expression::Number(n) => {
+				self.emit_constant(Value::Number(n))?;
+			},
+			Expression::Literal(ref s) => {
+				let string = self.heap.intern(s);
+				self.emit_constant(Value::Obj(string))?;
+			},
+			Expression::True => { self.emit_op(OpCode::True); },
+			Expression::False => { self.emit_op(OpCode::False); },
+			Expression::Nil => { self.emit_op(OpCode::Nil); },
+			Expression::Grouping(ref be) => {
+				self.expression(be)?;
+			},
+			Expression::Unary(ref operator, ref be) => {
+				self.expression(be)?;
+				self.set_token(operator);
+				match operator.token_type() {
+					TokenType::Bang => self.emit_op(OpCode::Not),
+					_ => self.emit_op(OpCode::Negate),
+				}
+			},
+			Expression::Binary(ref bel, ref operator, ref ber) => {
+				self.expression(bel)?;
+				self.expression(ber)?;
+				self.set_token(operator);
+				match operator.token_type() {
+					TokenType::Plus => self.emit_op(OpCode::Add),
+					TokenType::Minus => self.emit_op(OpCode::Subtract),
+					TokenType::Star => self.emit_op(OpCode::Multiply),
+					TokenType::Slash => self.emit_op(OpCode::Divide),
+					TokenType::EqualEqual => self.emit_op(OpCode::Equal),
+					TokenType::BangEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+					TokenType::Greater => self.emit_op(OpCode::Greater),
+					TokenType::GreaterEqual => self.emit_ops(OpCode::Less, OpCode::Not),
+					TokenType::Less => self.emit_op(OpCode::Less),
+					_ => self.emit_ops(OpCode::Greater, OpCode::Not),
+				}
+			},
+			Expression::Logical(ref bel, ref operator, ref ber) => {
+				self.expression(bel)?;
+				self.set_token(operator);
+				// the left operand stays on the stack as the result
+				// when it decides the outcome
+				if operator.token_type() == TokenType::And {
+					let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+					self.emit_op(OpCode::Pop);
+					self.expression(ber)?;
+					self.patch_jump(end_jump)?;
+				} else {
+					let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+					let end_jump = self.emit_jump(OpCode::Jump);
+					self.patch_jump(else_jump)?;
+					self.emit_op(OpCode::Pop);
+					self.expression(ber)?;
+					self.patch_jump(end_jump)?;
+				}
+			},
+			Expression::Variable(ref var_name, _) => {
+				self.set_token(var_name);
+				self.named_variable(&var_name.lexeme(), false)?;
+			},
+			Expression::Assign(ref var_name, ref be, _) => {
+				self.expression(be)?;
+				self.set_token(var_name);
+				self.named_variable(&var_name.lexeme(), true)?;
+			},
+			Expression::Call(ref callee, ref paren, ref arguments) => {
+				self.expression(callee)?;
+				for argument in arguments {
+					self.expression(argument)?;
+				}
+				self.set_token(paren);
+				self.emit_op(OpCode::Call);
+				self.emit_byte(arguments.len() as u8);
+			},
+			Expression::Get(ref object, ref prop_name) => {
+				self.expression(object)?;
+				self.set_token(prop_name);
+				let name = self.identifier_constant(&prop_name.lexeme())?;
+				self.emit_op(OpCode::GetProperty);
+				self.emit_short(name);
+			},
+			Expression::Set(ref object, ref prop_name, ref be) => {
+				self.expression(object)?;
+				self.expression(be)?;
+				self.set_token(prop_name);
+				let name = self.identifier_constant(&prop_name.lexeme())?;
+				self.emit_op(OpCode::SetProperty);
+				self.emit_short(name);
+			},
+			Expression::This(ref keyword, _) => {
+				self.set_token(keyword);
+				self.named_variable("this", false)?;
+			},
+			Expression::Super(ref keyword, ref method_name, _) => {
+				self.set_token(keyword);
+				self.named_variable("this", false)?;
+				self.named_variable("super", false)?;
+				self.set_token(method_name);
+				let name = self.identifier_constant(&method_name.lexeme())?;
+				self.emit_op(OpCode::GetSuper);
+				self.emit_short(name);
+			},
+		}
+		Ok(())
+	}
+
+	// variables
+
+	fn named_variable(&mut self, name: &str, assign: bool) -> Result<(), LoxError> {
+		let innermost = self.states.len() - 1;
+		let (get_op, set_op, operand) = if let Some(slot) = self.resolve_local(innermost, name) {
+			(OpCode::GetLocal, OpCode::SetLocal, slot)
+		} else if let Some(index) = self.resolve_upvalue(innermost, name)? {
+			(OpCode::GetUpvalue, OpCode::SetUpvalue, index)
+		} else {
+			let global = self.identifier_constant(name)?;
+			self.emit_op(if assign { OpCode::SetGlobal } else { OpCode::GetGlobal });
+			self.emit_short(global);
+			return Ok(());
+		};
+		self.emit_op(if assign { set_op } else { get_op });
+		self.emit_byte(operand);
+		Ok(())
+	}
+
+	fn resolve_local(&self, state_ix: usize, name: &str) -> Option<u8> {
+		let locals = &self.states[state_ix].locals;
+		for (slot, local) in locals.iter().enumerate().rev() {
+			if local.name == name {
+				return Some(slot as u8);
+			}
+		}
+		None
+	}
+
+	// looks outwards through the enclosing functions, threading an upvalue
+	// through each one between the variable's function and this one
+	fn resolve_upvalue(&mut self, state_ix: usize, name: &str) -> Result<Option<u8>, LoxError> {
+		if state_ix == 0 {
+			return Ok(None);
+		}
+		if let Some(slot) = self.resolve_local(state_ix - 1, name) {
+			self.states[state_ix - 1].locals[slot as usize].is_captured = true;
+			return self.add_upvalue(state_ix, slot, true).map(Some);
+		}
+		if let Some(index) = self.resolve_upvalue(state_ix - 1, name)? {
+			return self.add_upvalue(state_ix, index, false).map(Some);
+		}
+		Ok(None)
+	}
+
+	fn add_upvalue(&mut self, state_ix: usize, index: u8, is_local: bool) -> Result<u8, LoxError> {
+		let upvalues = &mut self.states[state_ix].upvalues;
+		for (i, upvalue) in upvalues.iter().enumerate() {
+			if upvalue.index == index && upvalue.is_local == is_local {
+				return Ok(i as u8);
+			}
+		}
+		if upvalues.len() >= MAX_UPVALUES {
+			return Err(LoxError::compile(&self.token, "Too many closure variables in function."));
+		}
+		upvalues.push(UpvalueRef { index, is_local });
+		Ok((upvalues.len() - 1) as u8)
+	}
+
+	// returns the name's constant for a global, which locals don't need
+	fn declare_variable(&mut self, var_name: &Token) -> Result<Option<u16>, LoxError> {
+		if self.current().scope_depth == 0 {
+			return self.identifier_constant(&var_name.lexeme()).map(Some);
+		}
+		self.add_local(&var_name.lexeme())?;
+		Ok(None)
+	}
+
+	fn define_variable(&mut self, global: Option<u16>) {
+		match global {
+			Some(global) => {
+				self.emit_op(OpCode::DefineGlobal);
+				self.emit_short(global);
+			},
+			// a local's value is already sitting in its stack slot
+			None => self.mark_initialized(),
+		}
+	}
+
+	fn add_local(&mut self, name: &str) -> Result<(), LoxError> {
+		if self.current().locals.len() >= MAX_LOCALS {
+			return Err(LoxError::compile(&self.token, "Too many local variables in function."));
+		}
+		self.current().locals.push(Local { name: name.to_string(), depth: None, is_captured: false });
+		Ok(())
+	}
+
+	fn mark_initialized(&mut self) {
+		let state = self.current();
+		if state.scope_depth == 0 {
+			return;
+		}
+		let depth = state.scope_depth;
+		if let Some(local) = state.locals.last_mut() {
+			local.depth = Some(depth);
+		}
+	}
+
+	fn begin_scope(&mut self) {
+		self.current().scope_depth += 1;
+	}
+
+	fn end_scope(&mut self) {
+		self.current().scope_depth -= 1;
+		loop {
+			let state = self.current();
+			let is_captured = match state.locals.last() {
+				Some(local) if local.depth.is_none_or(|depth| depth > state.scope_depth) => local.is_captured,
+				_ => break,
+			};
+			state.locals.pop();
+			// captured variables move off the stack into their upvalue
+			self.emit_op(if is_captured { OpCode::CloseUpvalue } else { OpCode::Pop });
+		}
+	}
+
+	// emitting code
+
+	fn set_token(&mut self, token: &Token) {
+		self.token = token.clone();
+	}
+
+	fn emit_byte(&mut self, byte: u8) {
+		let token = self.token.clone();
+		self.current().chunk.write(byte, &token);
+	}
+
+	fn emit_op(&mut self, op: OpCode) {
+		self.emit_byte(op as u8);
+	}
+
+	fn emit_ops(&mut self, first: OpCode, second: OpCode) {
+		self.emit_op(first);
+		self.emit_op(second);
+	}
+
+	fn emit_short(&mut self, short: u16) {
+		self.emit_byte((short >> 8) as u8);
+		self.emit_byte(short as u8);
+	}
+
+	fn emit_return(&mut self) {
+		if self.current().function_type == FunctionType::Initializer {
+			self.emit_op(OpCode::GetLocal);
+			self.emit_byte(0);
+		} else {
+			self.emit_op(OpCode::Nil);
+		}
+		self.emit_op(OpCode::Return);
+	}
+
+	fn make_constant(&mut self, value: Value) -> Result<u16, LoxError> {
+		let constant = self.current().chunk.add_constant(value);
+		if constant > u16::MAX as usize {
+			return Err(LoxError::compile(&self.token, "Too many constants in one chunk."));
+		}
+		Ok(constant as u16)
+	}
+
+	fn emit_constant(&mut self, value: Value) -> Result<(), LoxError> {
+		let constant = self.make_constant(value)?;
+		self.emit_op(OpCode::Constant);
+		self.emit_short(constant);
+		Ok(())
+	}
+
+	fn identifier_constant(&mut self, name: &str) -> Result<u16, LoxError> {
+		if let Some(&constant) = self.current().identifiers.get(name) {
+			return Ok(constant);
+		}
+		let string = self.heap.intern(name);
+		let constant = self.make_constant(Value::Obj(string))?;
+		self.current().identifiers.insert(name.to_string(), constant);
+		Ok(constant)
+	}
+
+	// jumps are emitted with a placeholder offset, filled in once the
+	// target is known
+	fn emit_jump(&mut self, op: OpCode) -> usize {
+		self.emit_op(op);
+		self.emit_short(0xffff);
+		self.current().chunk.code.len() - 2
+	}
+
+	fn patch_jump(&mut self, offset: usize) -> Result<(), LoxError> {
+		let jump = self.current().chunk.code.len() - offset - 2;
+		if jump > u16::MAX as usize {
+			return Err(LoxError::compile(&self.token, "Too much code to jump over."));
+		}
+		let code = &mut self.current().chunk.code;
+		code[offset] = (jump >> 8) as u8;
+		code[offset + 1] = jump as u8;
+		Ok(())
+	}
+
+	fn emit_loop(&mut self, loop_start: usize) -> Result<(), LoxError> {
+		self.emit_op(OpCode::Loop);
+		let offset = self.current().chunk.code.len() - loop_start + 2;
+		if offset > u16::MAX as usize {
+			return Err(LoxError::compile(&self.token, "Loop body too large."));
+		}
+		self.emit_short(offset as u16);
+		Ok(())
+	}
+}
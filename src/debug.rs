@@ -0,0 +1,105 @@
+use chunk::{Chunk, OpCode};
+use heap::{Heap, ObjRef};
+use object::{Obj, Value};
+
+// Human-readable listings of compiled bytecode, in the layout clox uses:
+// offset, source line ("|" when unchanged), opcode, then its operands.
+
+// lists the function's chunk followed by those of every function nested in it
+pub fn disassemble_function(heap: &Heap, function: ObjRef) -> String {
+	let function = heap.function(function);
+	let name = function.to_string();
+	let mut listing = disassemble_chunk(heap, &function.chunk, &name);
+	for &constant in &function.chunk.constants {
+		if let Value::Obj(obj_ref) = constant {
+			if let Obj::Function(_) = *heap.get(obj_ref) {
+				listing.push_str(&disassemble_function(heap, obj_ref));
+			}
+		}
+	}
+	listing
+}
+
+pub fn disassemble_chunk(heap: &Heap, chunk: &Chunk, name: &str) -> String {
+	let mut listing = format!("== {} ==\n", name);
+	let mut offset = 0;
+	while offset < chunk.code.len() {
+		let (text, next) = disassemble_instruction(heap, chunk, offset);
+		listing.push_str(&text);
+		listing.push('\n');
+		offset = next;
+	}
+	listing
+}
+
+// returns the instruction's listing and the offset of the next one
+pub fn disassemble_instruction(heap: &Heap, chunk: &Chunk, offset: usize) -> (String, usize) {
+	let mut text = format!("{:04} ", offset);
+	if offset > 0 && chunk.line(offset) == chunk.line(offset - 1) {
+		text.push_str("   | ");
+	} else {
+		text.push_str(&format!("{:4} ", chunk.line(offset)));
+	}
+
+	let op = match OpCode::from_byte(chunk.code[offset]) {
+		Some(op) => op,
+		None => {
+			text.push_str(&format!("Unknown opcode {}", chunk.code[offset]));
+			return (text, offset + 1);
+		},
+	};
+	let name = op_name(op);
+	match op {
+		OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::SetGlobal |
+		OpCode::GetProperty | OpCode::SetProperty | OpCode::GetSuper |
+		OpCode::Class | OpCode::Method => {
+			let constant = chunk.read_short(offset + 1) as usize;
+			text.push_str(&format!("{:<16} {:4} '{}'", name, constant, heap.format(chunk.constants[constant])));
+			(text, offset + 3)
+		},
+		OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::SetUpvalue | OpCode::Call => {
+			text.push_str(&format!("{:<16} {:4}", name, chunk.code[offset + 1]));
+			(text, offset + 2)
+		},
+		OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+			let jump = chunk.read_short(offset + 1) as usize;
+			let target = if op == OpCode::Loop { offset + 3 - jump } else { offset + 3 + jump };
+			text.push_str(&format!("{:<16} {:4} -> {}", name, offset, target));
+			(text, offset + 3)
+		},
+		OpCode::Closure => {
+			let constant = chunk.read_short(offset + 1) as usize;
+			let function = chunk.constants[constant];
+			text.push_str(&format!("{:<16} {:4} {}", name, constant, heap.format(function)));
+			let mut next = offset + 3;
+			if let Value::Obj(function) = function {
+				// each captured variable is a pair of operand bytes
+				for _ in 0..heap.function(function).upvalue_count {
+					let mut kind = "upvalue";
+					if chunk.code[next] == 1 {
+						kind = "local";
+					}
+					text.push_str(&format!("\n{:04}      |                     {} {}", next, kind, chunk.code[next + 1]));
+					next += 2;
+				}
+			}
+			(text, next)
+		},
+		_ => {
+			text.push_str(&name);
+			(text, offset + 1)
+		},
+	}
+}
+
+// GetLocal -> OP_GET_LOCAL
+fn op_name(op: OpCode) -> String {
+	let mut name = "OP".to_string();
+	for c in format!("{:?}", op).chars() {
+		if c.is_uppercase() {
+			name.push('_');
+		}
+		name.push(c.to_ascii_uppercase());
+	}
+	name
+}
@@ -0,0 +1,91 @@
+use error::LoxError;
+use intern::Symbol;
+use interpret::Value;
+use scanner::Token;
+use std::cell::RefCell;
+use std::collections::hash_map::{self, HashMap};
+use std::rc::Rc;
+
+// Names are interned symbols, so lookups hash and compare pointers
+// rather than text.
+pub struct Environment {
+	values: HashMap<Symbol, Value>,
+	enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+	pub fn new() -> Self {
+		Environment { values: HashMap::new(), enclosing: None }
+	}
+
+	pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+		Environment { values: HashMap::new(), enclosing: Some(enclosing) }
+	}
+
+	pub fn define(&mut self, var_name: Symbol, var_val: Value) {
+		self.values.insert(var_name, var_val);
+	}
+
+	pub fn get(&self, var_name: &Token) -> Result<Value, LoxError> {
+		if let Some(var_val) = self.values.get(var_name.symbol()) {
+			return Ok(var_val.clone());
+		}
+		if let Some(ref enclosing) = self.enclosing {
+			return enclosing.borrow().get(var_name);
+		}
+		Err(undefined_variable(var_name))
+	}
+
+	// assignment never creates a binding; it updates the nearest scope
+	// that already defines the name
+	pub fn assign(&mut self, var_name: &Token, var_val: Value) -> Result<(), LoxError> {
+		if let Some(slot) = self.values.get_mut(var_name.symbol()) {
+			*slot = var_val;
+			return Ok(());
+		}
+		if let Some(ref enclosing) = self.enclosing {
+			return enclosing.borrow_mut().assign(var_name, var_val);
+		}
+		Err(undefined_variable(var_name))
+	}
+
+	pub fn values(&self) -> hash_map::Values<'_, Symbol, Value> {
+		self.values.values()
+	}
+
+	pub fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+		self.enclosing.as_ref()
+	}
+
+	// resolved lookups go straight to the scope the resolver found,
+	// counting outward from this one
+	pub fn get_at(env: &Rc<RefCell<Environment>>, distance: usize, var_name: &Symbol) -> Option<Value> {
+		let scope = Environment::ancestor(env, distance);
+		let var_val = scope.borrow().values.get(var_name).cloned();
+		var_val
+	}
+
+	pub fn assign_at(env: &Rc<RefCell<Environment>>, distance: usize, var_name: Symbol, var_val: Value) {
+		Environment::ancestor(env, distance).borrow_mut().values.insert(var_name, var_val);
+	}
+
+	fn ancestor(env: &Rc<RefCell<Environment>>, distance: usize) -> Rc<RefCell<Environment>> {
+		let mut scope = env.clone();
+		for _ in 0..distance {
+			let enclosing = scope.borrow().enclosing.clone()
+				.expect("resolved scope depth deeper than environment chain");
+			scope = enclosing;
+		}
+		scope
+	}
+}
+
+impl Default for Environment {
+	fn default() -> Self {
+		Environment::new()
+	}
+}
+
+fn undefined_variable(var_name: &Token) -> LoxError {
+	LoxError::runtime(var_name, &format!("Undefined variable '{}'.", var_name.lexeme()))
+}
@@ -0,0 +1,107 @@
+use scanner::{Token, TokenType};
+use std::error;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorKind {
+	Lexical,
+	Syntax,
+	Resolution,
+	// limits of the bytecode format, e.g. too many constants
+	Compile,
+	Runtime,
+}
+
+// one active call at the time of a runtime error, innermost first
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+	// None for top-level code
+	pub function: Option<String>,
+	pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoxError {
+	pub kind: ErrorKind,
+	pub message: String,
+	pub line: usize,
+	pub column: usize,
+	// empty when the error was found at the end of the input
+	pub lexeme: String,
+	// filled in by the interpreter for runtime errors
+	pub trace: Vec<StackFrame>,
+}
+
+impl LoxError {
+	pub fn new(kind: ErrorKind, token: &Token, message: &str) -> Self {
+		let mut lexeme = token.lexeme();
+		if token.token_type() == TokenType::Eof {
+			lexeme = String::new();
+		}
+		LoxError {
+			kind,
+			message: message.to_string(),
+			line: token.line(),
+			column: token.column(),
+			lexeme,
+			trace: vec![],
+		}
+	}
+
+	pub fn lexical(line: usize, column: usize, lexeme: &str, message: &str) -> Self {
+		LoxError {
+			kind: ErrorKind::Lexical,
+			message: message.to_string(),
+			line,
+			column,
+			lexeme: lexeme.to_string(),
+			trace: vec![],
+		}
+	}
+
+	pub fn syntax(token: &Token, message: &str) -> Self {
+		LoxError::new(ErrorKind::Syntax, token, message)
+	}
+
+	pub fn resolution(token: &Token, message: &str) -> Self {
+		LoxError::new(ErrorKind::Resolution, token, message)
+	}
+
+	pub fn compile(token: &Token, message: &str) -> Self {
+		LoxError::new(ErrorKind::Compile, token, message)
+	}
+
+	pub fn runtime(token: &Token, message: &str) -> Self {
+		LoxError::new(ErrorKind::Runtime, token, message)
+	}
+}
+
+impl fmt::Display for LoxError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "[line {}:{}] ", self.line, self.column)?;
+		// the message already says which characters were at fault
+		if self.kind == ErrorKind::Lexical {
+			return write!(f, "Error: {}", self.message);
+		}
+		if self.kind == ErrorKind::Runtime {
+			write!(f, "Runtime error")?;
+		} else {
+			write!(f, "Error")?;
+		}
+		if self.lexeme.is_empty() {
+			write!(f, " at end: {}", self.message)?;
+		} else {
+			write!(f, " at '{}': {}", self.lexeme, self.message)?;
+		}
+		for frame in &self.trace {
+			match frame.function {
+				Some(ref name) => write!(f, "\n[line {}] in {}()", frame.line, name)?,
+				None => write!(f, "\n[line {}] in script", frame.line)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+// lets hosts pass a Lox error on with `?`, e.g. as a `Box<dyn Error>`
+impl error::Error for LoxError {}
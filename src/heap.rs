@@ -0,0 +1,282 @@
+use object::{Class, Closure, Function, Obj, Upvalue, Value};
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+// collect once this much has been allocated, then whenever the heap has
+// grown by HEAP_GROW_FACTOR since the last collection
+const INITIAL_GC_THRESHOLD: usize = 1024 * 1024;
+const HEAP_GROW_FACTOR: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+struct Entry {
+	obj: Obj,
+	// rough footprint, counted towards the next collection
+	size: usize,
+	marked: bool,
+}
+
+// The VM's managed heap: a mark-and-sweep collector over an arena of
+// objects. Freed slots are reused, so handles stay valid only while the
+// object is reachable from a root the VM marked.
+pub struct Heap {
+	entries: Vec<Option<Entry>>,
+	free: Vec<usize>,
+	bytes_allocated: usize,
+	next_gc: usize,
+	// objects marked but not yet traced
+	gray: Vec<ObjRef>,
+	// every live string, by contents; doesn't keep them alive itself
+	strings: HashMap<Rc<str>, ObjRef>,
+	// collect before every allocation, to shake out missing roots
+	pub stress: bool,
+}
+
+impl Heap {
+	pub fn new() -> Self {
+		Heap {
+			entries: vec![],
+			free: vec![],
+			bytes_allocated: 0,
+			next_gc: INITIAL_GC_THRESHOLD,
+			gray: vec![],
+			strings: HashMap::new(),
+			stress: false,
+		}
+	}
+
+	// never collects by itself; the VM checks `should_collect` first,
+	// since only it knows the roots
+	pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+		let size = object_size(&obj);
+		self.bytes_allocated += size;
+		let entry = Some(Entry { obj, size, marked: false });
+		match self.free.pop() {
+			Some(ix) => {
+				self.entries[ix] = entry;
+				ObjRef(ix)
+			},
+			None => {
+				self.entries.push(entry);
+				ObjRef(self.entries.len() - 1)
+			},
+		}
+	}
+
+	// the existing object for a string, if any
+	pub fn find_string(&self, s: &str) -> Option<ObjRef> {
+		self.strings.get(s).cloned()
+	}
+
+	pub fn intern(&mut self, s: &str) -> ObjRef {
+		if let Some(string) = self.find_string(s) {
+			return string;
+		}
+		let text: Rc<str> = Rc::from(s);
+		let string = self.alloc(Obj::String(text.clone()));
+		self.strings.insert(text, string);
+		string
+	}
+
+	pub fn should_collect(&self) -> bool {
+		self.stress || self.bytes_allocated > self.next_gc
+	}
+
+	pub fn live_objects(&self) -> usize {
+		self.entries.len() - self.free.len()
+	}
+
+	pub fn get(&self, obj_ref: ObjRef) -> &Obj {
+		match self.entries[obj_ref.0] {
+			Some(ref entry) => &entry.obj,
+			None => panic!("use of a collected object"),
+		}
+	}
+
+	pub fn get_mut(&mut self, obj_ref: ObjRef) -> &mut Obj {
+		match self.entries[obj_ref.0] {
+			Some(ref mut entry) => &mut entry.obj,
+			None => panic!("use of a collected object"),
+		}
+	}
+
+	// garbage collection
+
+	pub fn mark_value(&mut self, value: Value) {
+		if let Value::Obj(obj_ref) = value {
+			self.mark_object(obj_ref);
+		}
+	}
+
+	pub fn mark_object(&mut self, obj_ref: ObjRef) {
+		if let Some(ref mut entry) = self.entries[obj_ref.0] {
+			if entry.marked {
+				return;
+			}
+			entry.marked = true;
+		}
+		self.gray.push(obj_ref);
+	}
+
+	// call once the roots are marked: traces everything reachable from
+	// them and frees the rest
+	pub fn collect(&mut self) {
+		while let Some(obj_ref) = self.gray.pop() {
+			self.blacken(obj_ref);
+		}
+		// the strings table is weak: strings nothing else reaches are dropped
+		let entries = &self.entries;
+		self.strings.retain(|_, string| match entries[string.0] {
+			Some(ref entry) => entry.marked,
+			None => false,
+		});
+		self.sweep();
+		self.next_gc = (self.bytes_allocated * HEAP_GROW_FACTOR).max(INITIAL_GC_THRESHOLD);
+	}
+
+	fn blacken(&mut self, obj_ref: ObjRef) {
+		let mut children = vec![];
+		let mut values = vec![];
+		match *self.get(obj_ref) {
+			Obj::String(_) | Obj::Native(_) => {},
+			Obj::Function(ref function) => values.extend(function.chunk.constants.iter().cloned()),
+			Obj::Closure(ref closure) => {
+				children.push(closure.function);
+				children.extend(closure.upvalues.iter().cloned());
+			},
+			Obj::Upvalue(Upvalue::Closed(value)) => values.push(value),
+			// an open upvalue's variable is on the stack, which is a root
+			Obj::Upvalue(Upvalue::Open(_)) => {},
+			Obj::Class(ref class) => {
+				children.push(class.name);
+				for (&name, &method) in &class.methods {
+					children.push(name);
+					children.push(method);
+				}
+			},
+			Obj::Instance(ref instance) => {
+				children.push(instance.class);
+				children.extend(instance.fields.keys().cloned());
+				values.extend(instance.fields.values().cloned());
+			},
+			Obj::BoundMethod(ref bound) => {
+				children.push(bound.method);
+				values.push(bound.receiver);
+			},
+		}
+		for child in children {
+			self.mark_object(child);
+		}
+		for value in values {
+			self.mark_value(value);
+		}
+	}
+
+	fn sweep(&mut self) {
+		for (ix, slot) in self.entries.iter_mut().enumerate() {
+			let reachable = match *slot {
+				Some(ref mut entry) => mem::replace(&mut entry.marked, false),
+				None => continue,
+			};
+			if !reachable {
+				if let Some(entry) = slot.take() {
+					self.bytes_allocated -= entry.size;
+				}
+				self.free.push(ix);
+			}
+		}
+	}
+
+	// the typed accessors below are only used where the compiler guarantees
+	// the object's kind, so a mismatch is a bug in the VM
+	pub fn string(&self, obj_ref: ObjRef) -> &str {
+		match *self.get(obj_ref) {
+			Obj::String(ref s) => s,
+			_ => panic!("expected a string object"),
+		}
+	}
+
+	pub fn function(&self, obj_ref: ObjRef) -> &Function {
+		match *self.get(obj_ref) {
+			Obj::Function(ref function) => function,
+			_ => panic!("expected a function object"),
+		}
+	}
+
+	pub fn closure(&self, obj_ref: ObjRef) -> &Closure {
+		match *self.get(obj_ref) {
+			Obj::Closure(ref closure) => closure,
+			_ => panic!("expected a closure object"),
+		}
+	}
+
+	pub fn upvalue_mut(&mut self, obj_ref: ObjRef) -> &mut Upvalue {
+		match *self.get_mut(obj_ref) {
+			Obj::Upvalue(ref mut upvalue) => upvalue,
+			_ => panic!("expected an upvalue object"),
+		}
+	}
+
+	pub fn class(&self, obj_ref: ObjRef) -> &Class {
+		match *self.get(obj_ref) {
+			Obj::Class(ref class) => class,
+			_ => panic!("expected a class object"),
+		}
+	}
+
+	pub fn class_mut(&mut self, obj_ref: ObjRef) -> &mut Class {
+		match *self.get_mut(obj_ref) {
+			Obj::Class(ref mut class) => class,
+			_ => panic!("expected a class object"),
+		}
+	}
+
+	pub fn as_string(&self, value: Value) -> Option<&str> {
+		match value {
+			Value::Obj(obj_ref) => match *self.get(obj_ref) {
+				Obj::String(ref s) => Some(s),
+				_ => None,
+			},
+			_ => None,
+		}
+	}
+
+	// formats a value the way the tree-walker prints it
+	pub fn format(&self, value: Value) -> String {
+		match value {
+			Value::Nil => "nil".to_string(),
+			Value::Boolean(b) => b.to_string(),
+			Value::Number(n) => n.to_string(),
+			Value::Obj(obj_ref) => match *self.get(obj_ref) {
+				Obj::String(ref s) => s.to_string(),
+				Obj::Function(ref function) => function.to_string(),
+				Obj::Native(ref native) => format!("<native fn {}>", native.name),
+				Obj::Closure(ref closure) => self.function(closure.function).to_string(),
+				Obj::Upvalue(_) => "upvalue".to_string(),
+				Obj::Class(ref class) => self.string(class.name).to_string(),
+				Obj::Instance(ref instance) => format!("{} instance", self.string(self.class(instance.class).name)),
+				Obj::BoundMethod(ref bound) => self.format(Value::Obj(bound.method)),
+			},
+		}
+	}
+}
+
+impl Default for Heap {
+	fn default() -> Self {
+		Heap::new()
+	}
+}
+
+// what an object owns at allocation time; tables that grow later aren't
+// tracked, so this only paces collections
+fn object_size(obj: &Obj) -> usize {
+	let contents = match *obj {
+		Obj::String(ref s) => s.len(),
+		Obj::Function(ref function) => function.chunk.code.len() + function.chunk.constants.len() * mem::size_of::<Value>(),
+		Obj::Closure(ref closure) => closure.upvalues.len() * mem::size_of::<ObjRef>(),
+		_ => 0,
+	};
+	mem::size_of::<Entry>() + contents
+}
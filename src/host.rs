@@ -0,0 +1,18 @@
+use std::fmt;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// What both backends need from the host they run in.
+
+// seconds since the Unix epoch, for the `clock` native
+pub fn clock() -> Result<f64, String> {
+	let elapsed = SystemTime::now().duration_since(UNIX_EPOCH)
+		.map_err(|_| "System clock is set before the Unix epoch.".to_string())?;
+	Ok(elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9)
+}
+
+// writes to a program's output sink, which belongs to the host; a failed
+// write becomes a Lox runtime error rather than taking the host down
+pub fn write_output(output: &mut dyn Write, args: fmt::Arguments) -> Result<(), String> {
+	output.write_fmt(args).map_err(|e| format!("Could not write output: {}.", e))
+}
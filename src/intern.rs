@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::rc::Rc;
+
+// purge unused strings once the table has doubled since the last purge
+const INITIAL_PURGE_SIZE: usize = 1024;
+
+/// An immutable string shared through an [`Interner`]. Symbols from the
+/// same interner are equal exactly when they are the same allocation, so
+/// comparing and hashing them never looks at the text.
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+
+impl Symbol {
+	pub fn as_str(&self) -> &str {
+		&self.0
+	}
+}
+
+impl PartialEq for Symbol {
+	fn eq(&self, other: &Symbol) -> bool {
+		Rc::ptr_eq(&self.0, &other.0)
+	}
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		(self.0.as_ptr() as usize).hash(state);
+	}
+}
+
+impl Deref for Symbol {
+	type Target = str;
+
+	fn deref(&self) -> &str {
+		&self.0
+	}
+}
+
+impl fmt::Display for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", &*self.0)
+	}
+}
+
+impl fmt::Debug for Symbol {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?}", &*self.0)
+	}
+}
+
+/// A string made outside any interpreter, e.g. by a native function. It
+/// equals no other symbol until an interpreter interns it, though as a
+/// `Value` it equals any string with the same text.
+impl<'a> From<&'a str> for Symbol {
+	fn from(s: &'a str) -> Symbol {
+		Symbol(Rc::from(s))
+	}
+}
+
+impl From<String> for Symbol {
+	fn from(s: String) -> Symbol {
+		Symbol(Rc::from(s))
+	}
+}
+
+struct Table {
+	strings: HashSet<Rc<str>>,
+	purge_at: usize,
+}
+
+/// A table of interned strings. Cloning it gives another handle to the
+/// same table, so the scanner and interpreter can share one.
+#[derive(Clone)]
+pub struct Interner {
+	table: Rc<RefCell<Table>>,
+}
+
+impl Interner {
+	pub fn new() -> Self {
+		let table = Table { strings: HashSet::new(), purge_at: INITIAL_PURGE_SIZE };
+		Interner { table: Rc::new(RefCell::new(table)) }
+	}
+
+	pub fn intern(&self, s: &str) -> Symbol {
+		let mut table = self.table.borrow_mut();
+		if let Some(interned) = table.strings.get(s) {
+			return Symbol(interned.clone());
+		}
+		if table.strings.len() >= table.purge_at {
+			// strings only the table still refers to can go
+			table.strings.retain(|interned| Rc::strong_count(interned) > 1);
+			table.purge_at = (table.strings.len() * 2).max(INITIAL_PURGE_SIZE);
+		}
+		let interned: Rc<str> = Rc::from(s);
+		table.strings.insert(interned.clone());
+		Symbol(interned)
+	}
+}
+
+impl Default for Interner {
+	fn default() -> Self {
+		Interner::new()
+	}
+}
@@ -1,135 +1,772 @@
-use parse::{Expression, Statement};
-use scanner::TokenType;
+use environment::Environment;
+use host;
+use error::{ErrorKind, LoxError, StackFrame};
+use intern::{Interner, Symbol};
+use parse::{Expression, FunctionDecl, Statement};
+use scanner::{Token, TokenType};
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::ptr;
+use std::rc::Rc;
 
-#[derive(Debug, PartialEq, Clone)]
+mod cycles;
+
+use self::cycles::Cycles;
+
+#[derive(Debug, Clone)]
 pub enum Value {
 	Number(f64),
-	StringLiteral(String),
+	// interned, so equal strings are one allocation and compare by pointer
+	StringLiteral(Symbol),
 	Boolean(bool),
 	Nil,
+	Function(Rc<LoxFunction>),
+	NativeFunction(Rc<NativeFunction>),
+	Class(Rc<LoxClass>),
+	Instance(Rc<RefCell<LoxInstance>>),
+}
+
+// strings are equal when their text is, which for interned ones is a pointer
+// comparison, while a string made by the host has to be compared by text;
+// functions, classes and instances are equal only to themselves
+impl PartialEq for Value {
+	fn eq(&self, other: &Value) -> bool {
+		match (self, other) {
+			(Value::Number(n_l), Value::Number(n_r)) => n_l == n_r,
+			(Value::StringLiteral(s_l), Value::StringLiteral(s_r)) => s_l == s_r || s_l.as_str() == s_r.as_str(),
+			(Value::Boolean(b_l), Value::Boolean(b_r)) => b_l == b_r,
+			(Value::Nil, Value::Nil) => true,
+			(Value::Function(f_l), Value::Function(f_r)) => f_l == f_r,
+			(Value::NativeFunction(f_l), Value::NativeFunction(f_r)) => f_l == f_r,
+			(Value::Class(c_l), Value::Class(c_r)) => c_l == c_r,
+			(Value::Instance(i_l), Value::Instance(i_r)) => i_l == i_r,
+			_ => false,
+		}
+	}
+}
+
+// how values look to Lox programs, as produced by `print`
+impl fmt::Display for Value {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			// f64's Display already leaves off a trailing ".0"
+			Value::Number(n) => write!(f, "{}", n),
+			Value::StringLiteral(ref s) => write!(f, "{}", s),
+			Value::Boolean(b) => write!(f, "{}", b),
+			Value::Nil => write!(f, "nil"),
+			Value::Function(ref function) => write!(f, "{:?}", function),
+			Value::NativeFunction(ref native) => write!(f, "{:?}", native),
+			Value::Class(ref class) => write!(f, "{:?}", class),
+			Value::Instance(ref instance) => write!(f, "{:?}", instance.borrow()),
+		}
+	}
+}
+
+pub struct LoxFunction {
+	// shared with the tree, so making and binding functions copies nothing
+	declaration: Rc<FunctionDecl>,
+	// the scope the function was declared in, shared with anything else
+	// that captured it and kept alive for as long as the function is
+	closure: Rc<RefCell<Environment>>,
+	is_initializer: bool,
+}
+
+impl LoxFunction {
+	pub fn arity(&self) -> usize {
+		self.declaration.params.len()
+	}
+
+	// the same function closing over `this_env`, a scope just inside its
+	// own where `this` is bound
+	fn bind(&self, this_env: Rc<RefCell<Environment>>) -> LoxFunction {
+		LoxFunction {
+			declaration: self.declaration.clone(),
+			closure: this_env,
+			is_initializer: self.is_initializer,
+		}
+	}
+}
+
+// functions compare by identity, not by their source
+impl PartialEq for LoxFunction {
+	fn eq(&self, other: &LoxFunction) -> bool {
+		ptr::eq(self, other)
+	}
+}
+
+impl fmt::Debug for LoxFunction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "<fn {}>", self.declaration.name.lexeme())
+	}
+}
+
+// a host function callable from Lox; an Err message becomes a Lox runtime error
+type NativeFn = Box<dyn Fn(&[Value]) -> Result<Value, String>>;
+
+pub struct NativeFunction {
+	name: String,
+	arity: usize,
+	function: NativeFn,
+}
+
+impl NativeFunction {
+	pub fn arity(&self) -> usize {
+		self.arity
+	}
+}
+
+impl PartialEq for NativeFunction {
+	fn eq(&self, other: &NativeFunction) -> bool {
+		ptr::eq(self, other)
+	}
+}
+
+impl fmt::Debug for NativeFunction {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "<native fn {}>", self.name)
+	}
+}
+
+pub struct LoxClass {
+	name: String,
+	superclass: Option<Rc<LoxClass>>,
+	methods: HashMap<Symbol, Rc<LoxFunction>>,
+	// `init`, found once when the class is declared
+	initializer: Option<Rc<LoxFunction>>,
+}
+
+impl LoxClass {
+	// methods not defined on the class itself are looked up the superclass chain
+	fn find_method(&self, method_name: &Symbol) -> Option<Rc<LoxFunction>> {
+		if let Some(method) = self.methods.get(method_name) {
+			return Some(method.clone());
+		}
+		if let Some(ref superclass) = self.superclass {
+			return superclass.find_method(method_name);
+		}
+		return None;
+	}
+
+	// calling a class forwards its arguments to `init`, if there is one
+	pub fn arity(&self) -> usize {
+		match self.initializer {
+			Some(ref initializer) => initializer.arity(),
+			None => 0,
+		}
+	}
+}
+
+impl PartialEq for LoxClass {
+	fn eq(&self, other: &LoxClass) -> bool {
+		ptr::eq(self, other)
+	}
+}
+
+impl fmt::Debug for LoxClass {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.name)
+	}
+}
+
+pub struct LoxInstance {
+	class: Rc<LoxClass>,
+	fields: HashMap<Symbol, Value>,
+}
+
+impl PartialEq for LoxInstance {
+	fn eq(&self, other: &LoxInstance) -> bool {
+		ptr::eq(self, other)
+	}
+}
+
+impl fmt::Debug for LoxInstance {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} instance", self.class.name)
+	}
+}
+
+fn undefined_property(prop_name: &Token) -> LoxError {
+	LoxError::runtime(prop_name, &format!("Undefined property '{}'.", prop_name.lexeme()))
+}
+
+// Lox calls recurse on the Rust stack, each taking up to about 16 KB in an
+// unoptimized build when its body nests deeply, so this many fit in the 2 MiB
+// a spawned thread gets by default, with room to spare for the host
+const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+// a Lox function call in progress
+struct CallFrame {
+	function: Symbol,
+	// where the caller is waiting for this call to return
+	call_line: usize,
 }
 
 pub struct Interpreter {
-	environment: HashMap<String, Value>,
+	globals: Rc<RefCell<Environment>>,
+	environment: Rc<RefCell<Environment>>,
+	frames: Vec<CallFrame>,
+	// calls deeper than this are a runtime error, not a crash
+	max_call_depth: usize,
+	// where `print` writes to
+	output: Box<dyn Write>,
+	// strings and names are interned here, shared with the scanner
+	interner: Interner,
+	// names the interpreter looks up itself
+	this_symbol: Symbol,
+	super_symbol: Symbol,
+	init_symbol: Symbol,
+	// scopes and instances, for freeing the cycles among them
+	cycles: Cycles,
 }
 
 impl Interpreter {
 	pub fn new() -> Self {
-		Interpreter { environment: HashMap::new() }
+		Interpreter::with_output(Box::new(io::stdout()))
+	}
+
+	pub fn with_output(output: Box<dyn Write>) -> Self {
+		let globals = Rc::new(RefCell::new(Environment::new()));
+		let interner = Interner::new();
+		let mut cycles = Cycles::new();
+		cycles.track_environment(&globals);
+		let mut interpreter = Interpreter {
+			globals: globals.clone(),
+			environment: globals,
+			frames: vec![],
+			max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+			output: output,
+			this_symbol: interner.intern("this"),
+			super_symbol: interner.intern("super"),
+			init_symbol: interner.intern("init"),
+			interner: interner,
+			cycles: cycles,
+		};
+
+		interpreter.define_native("clock", 0, |_| host::clock().map(Value::Number));
+
+		interpreter
+	}
+
+	// makes a Rust closure callable from Lox as a global function
+	pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+		where F: Fn(&[Value]) -> Result<Value, String> + 'static {
+		let native = NativeFunction { name: name.to_string(), arity: arity, function: Box::new(function) };
+		let name = self.interner.intern(name);
+		self.globals.borrow_mut().define(name, Value::NativeFunction(Rc::new(native)));
+	}
+
+	/// Sets how deep Lox calls may nest before a call fails with "Stack
+	/// overflow." The default, 100, is safe on any thread with the default
+	/// 2 MiB stack; a host running on a bigger stack can allow more.
+	pub fn set_max_call_depth(&mut self, depth: usize) {
+		self.max_call_depth = depth;
+	}
+
+	/// The table this interpreter interns names and strings in. Programs
+	/// must be scanned with it, via `Scanner::new`.
+	pub fn interner(&self) -> &Interner {
+		&self.interner
 	}
 
-	pub fn interpret(&mut self, program: Vec<Statement>) -> Result<(), &'static str> {
+	/// The number of scopes and instances the interpreter has made that are
+	/// still alive. Reference counting frees most of them as soon as they
+	/// are done with; cycles among them are reclaimed as their number grows:
+	///
+	/// ```
+	/// let mut interpreter = rustlox::Interpreter::new();
+	/// let program = rustlox::parse_program("
+	///     class Node { init() { this.next = this; } }
+	///     for (var i = 0; i < 100000; i = i + 1) { Node(); }
+	/// ", interpreter.interner()).unwrap();
+	/// interpreter.interpret(&program).unwrap();
+	/// assert!(interpreter.live_objects() < 100000);
+	/// ```
+	pub fn live_objects(&self) -> usize {
+		self.cycles.live_objects()
+	}
+
+	// every scope is made here, so the cycle collector knows of it; this
+	// is also where it gets to run, between statements and calls
+	fn new_environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+		if self.cycles.should_collect() {
+			self.cycles.collect();
+		}
+		let environment = Rc::new(RefCell::new(environment));
+		self.cycles.track_environment(&environment);
+		environment
+	}
+
+	fn new_instance(&mut self, class: Rc<LoxClass>) -> Rc<RefCell<LoxInstance>> {
+		if self.cycles.should_collect() {
+			self.cycles.collect();
+		}
+		let instance = Rc::new(RefCell::new(LoxInstance { class: class, fields: HashMap::new() }));
+		self.cycles.track_instance(&instance);
+		instance
+	}
+
+	// wraps a method's closure in a scope where `this` is the given instance
+	fn bind(&mut self, method: &LoxFunction, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+		let mut this_env = Environment::with_enclosing(method.closure.clone());
+		this_env.define(self.this_symbol.clone(), Value::Instance(instance));
+		method.bind(self.new_environment(this_env))
+	}
+
+	// fields shadow methods; methods come back bound to the instance
+	fn instance_get(&mut self, instance: Rc<RefCell<LoxInstance>>, prop_name: &Token) -> Result<Value, LoxError> {
+		if let Some(field_val) = instance.borrow().fields.get(prop_name.symbol()) {
+			return Ok(field_val.clone());
+		}
+		let method = instance.borrow().class.find_method(prop_name.symbol());
+		if let Some(method) = method {
+			return Ok(Value::Function(Rc::new(self.bind(&method, instance))));
+		}
+		return Err(undefined_property(prop_name));
+	}
+
+	// strings made by native functions join the table before Lox sees them
+	fn intern_value(&self, val: Value) -> Value {
+		match val {
+			Value::StringLiteral(s) => Value::StringLiteral(self.interner.intern(&s)),
+			_ => val,
+		}
+	}
+
+	// expects a program that has already been through the resolver;
+	// hands back the value of a trailing expression statement, for embedders
+	pub fn interpret(&mut self, program: &[Statement]) -> Result<Value, LoxError> {
+	    let mut last_val = Value::Nil;
 	    for stmt in program {
-	        self.execute(stmt)?;
+	        let result = match *stmt {
+	            Statement::ExprStmt(ref e) => self.evaluate(e),
+	            _ => self.execute(stmt).map(|_| Value::Nil),
+	        };
+	        match result {
+	            Ok(val) => { last_val = val; },
+	            Err(e) => {
+	                let e = self.with_trace(e);
+	                self.frames.clear();
+	                return Err(e);
+	            },
+	        }
 	    }
-	    Ok(())
+	    Ok(last_val)
 	}
 
-	fn execute(&mut self, stmt: Statement) -> Result<(), &'static str>{
-	    match stmt {
-	        Statement::ExprStmt(e) => { self.evaluate(e)?; },
-	        Statement::PrintStmt(e) => {
-	            let val = self.evaluate(e)?;
-	            println!("{:?}", val);
-	        },
-	        Statement::VarDecl(var_name, initializer) => {
-	        	let mut var_val = Value::Nil;
-	        	if initializer != Expression::Nil {
-	        		var_val = self.evaluate(initializer)?;
-	        	}
+	// records the call stack on a runtime error the first time it
+	// unwinds through a call, while every frame is still in place
+	fn with_trace(&self, mut error: LoxError) -> LoxError {
+		if error.kind != ErrorKind::Runtime || !error.trace.is_empty() {
+			return error;
+		}
+		let mut line = error.line;
+		for frame in self.frames.iter().rev() {
+			error.trace.push(StackFrame { function: Some(frame.function.to_string()), line: line });
+			line = frame.call_line;
+		}
+		error.trace.push(StackFrame { function: None, line: line });
+		error
+	}
 
-	        	self.environment.insert(var_name, var_val);
+	// a returned Some(value) means a return statement is unwinding
+	// towards the enclosing function call
+	fn execute(&mut self, stmt: &Statement) -> Result<Option<Value>, LoxError> {
+	    match *stmt {
+	        Statement::ExprStmt(ref e) => self.execute_expression(e),
+	        Statement::PrintStmt(ref keyword, ref e) => self.execute_print(keyword, e),
+	        Statement::VarDecl(ref var_name, ref initializer) => self.declare_variable(var_name, initializer),
+	        Statement::Block(ref statements) => {
+	        	let block_env = self.block_environment();
+	        	self.execute_block(statements, block_env)
+	        },
+	        Statement::If(ref condition, ref then_branch, ref else_branch) => self.execute_if(condition, then_branch, else_branch),
+	        Statement::While(ref condition, ref body) => self.execute_while(condition, body),
+	        Statement::Function(ref declaration) => self.declare_function(declaration),
+	        Statement::Class(ref class_name, ref superclass_expr, ref method_decls) => {
+	        	self.declare_class(class_name, superclass_expr, method_decls)
 	        },
+	        Statement::Return(_, ref e) => self.execute_return(e),
 	    }
-	    Ok(())
-	}
-
-	fn evaluate(&mut self, expr: Expression) -> Result<Value, &'static str> {
-		match expr {
-			Expression::Number(n) => Ok(Value::Number(n)),
-			Expression::Literal(s) => Ok(Value::StringLiteral(s)),
-			Expression::True => Ok(Value::Boolean(true)),
-			Expression::False => Ok(Value::Boolean(false)),
-			Expression::Nil => Ok(Value::Nil),
-			Expression::Unary(tt, be) => self.evaluate_unary(tt, *be),
-			Expression::Binary(bel, tt, ber) => self.evaluate_binary(*bel, tt, *ber),
-			Expression::Grouping(be) => self.evaluate(*be),
-	        Expression::Variable(var_name) => self.var_lookup(var_name),
-	        Expression::Assign(var_name, be) => {
-	        	if self.environment.contains_key(&var_name) {
-	        		let var_val = self.evaluate(*be)?;
-	        		self.environment.insert(var_name, var_val.clone());
-	        		return Ok(var_val);
-	        	} else {
-	        		return Err("Undefined variable");
-	        	}},
-		}
-	}
-
-	fn var_lookup(&self, var_name: String) -> Result<Value, &'static str> {
-		if let Some(var_val) = self.environment.get(&var_name) {
-			let owned_val = (*var_val).clone();
-			return Ok(owned_val);
-		}
-		return Err("undefined variable");
-	}
-
-	fn evaluate_unary(&mut self, operator: TokenType, expr: Expression) -> Result<Value, &'static str> {
-		match operator {
-			TokenType::Bang => {
-				let expr_value = self.evaluate(expr)?;
-				return Ok(Value::Boolean(!is_truthy(expr_value)));
+	}
+
+	// each kind of statement runs in a function of its own, so that the
+	// frames of `execute` nested on the Rust stack by Lox calls stay small;
+	// like `execute`, they give back a value only for `return`
+
+	fn execute_expression(&mut self, expr: &Expression) -> Result<Option<Value>, LoxError> {
+		self.evaluate(expr)?;
+		Ok(None)
+	}
+
+	fn block_environment(&mut self) -> Rc<RefCell<Environment>> {
+		let block_env = Environment::with_enclosing(self.environment.clone());
+		self.new_environment(block_env)
+	}
+
+	fn execute_print(&mut self, keyword: &Token, expr: &Expression) -> Result<Option<Value>, LoxError> {
+		let val = self.evaluate(expr)?;
+		host::write_output(&mut *self.output, format_args!("{}\n", val))
+			.map_err(|message| LoxError::runtime(keyword, &message))?;
+		Ok(None)
+	}
+
+	fn execute_if(&mut self, condition: &Expression, then_branch: &Statement, else_branch: &Option<Box<Statement>>) -> Result<Option<Value>, LoxError> {
+		if is_truthy(self.evaluate(condition)?) {
+			return self.execute(then_branch);
+		} else if let Some(ref else_branch) = *else_branch {
+			return self.execute(else_branch);
+		}
+		Ok(None)
+	}
+
+	fn execute_return(&mut self, expr: &Expression) -> Result<Option<Value>, LoxError> {
+		let return_val = self.evaluate(expr)?;
+		Ok(Some(return_val))
+	}
+
+	fn declare_variable(&mut self, var_name: &Token, initializer: &Expression) -> Result<Option<Value>, LoxError> {
+		let mut var_val = Value::Nil;
+		if *initializer != Expression::Nil {
+			var_val = self.evaluate(initializer)?;
+		}
+
+		self.environment.borrow_mut().define(var_name.symbol().clone(), var_val);
+		Ok(None)
+	}
+
+	fn execute_while(&mut self, condition: &Expression, body: &Statement) -> Result<Option<Value>, LoxError> {
+		while is_truthy(self.evaluate(condition)?) {
+			let result = self.execute(body);
+			match result {
+				Ok(None) => {},
+				_ => { return result; },
+			}
+		}
+		Ok(None)
+	}
+
+	fn declare_function(&mut self, declaration: &Rc<FunctionDecl>) -> Result<Option<Value>, LoxError> {
+		let fun_name = declaration.name.symbol().clone();
+		let function = LoxFunction {
+			declaration: declaration.clone(),
+			closure: self.environment.clone(),
+			is_initializer: false,
+		};
+		self.environment.borrow_mut().define(fun_name, Value::Function(Rc::new(function)));
+		Ok(None)
+	}
+
+	fn declare_class(&mut self, class_name: &Token, superclass_expr: &Option<Expression>, method_decls: &[Rc<FunctionDecl>]) -> Result<Option<Value>, LoxError> {
+		let mut superclass = None;
+		if let Some(ref superclass_expr) = *superclass_expr {
+			let superclass_name = match *superclass_expr {
+				Expression::Variable(ref var_name, _) => var_name.clone(),
+				_ => class_name.clone(),
+			};
+			if let Value::Class(class) = self.evaluate(superclass_expr)? {
+				superclass = Some(class);
+			} else {
+				return Err(LoxError::runtime(&superclass_name, "Superclass must be a class."));
+			}
+		}
+
+		// methods of a subclass close over an extra scope holding `super`
+		let mut method_env = self.environment.clone();
+		if let Some(ref superclass) = superclass {
+			let mut super_env = Environment::with_enclosing(self.environment.clone());
+			super_env.define(self.super_symbol.clone(), Value::Class(superclass.clone()));
+			method_env = self.new_environment(super_env);
+		}
+
+		let mut methods = HashMap::new();
+		for declaration in method_decls {
+			let method_name = declaration.name.symbol().clone();
+			let method = LoxFunction {
+				is_initializer: method_name == self.init_symbol,
+				declaration: declaration.clone(),
+				closure: method_env.clone(),
+			};
+			methods.insert(method_name, Rc::new(method));
+		}
+
+		let mut class = LoxClass { name: class_name.lexeme(), superclass: superclass, methods: methods, initializer: None };
+		class.initializer = class.find_method(&self.init_symbol);
+		self.environment.borrow_mut().define(class_name.symbol().clone(), Value::Class(Rc::new(class)));
+		Ok(None)
+	}
+
+	fn execute_block(&mut self, statements: &[Statement], environment: Rc<RefCell<Environment>>) -> Result<Option<Value>, LoxError> {
+		let previous = ::std::mem::replace(&mut self.environment, environment);
+		let mut result = Ok(None);
+		for stmt in statements {
+			result = self.execute(stmt);
+			match result {
+				Ok(None) => {},
+				_ => { break; },
+			}
+		}
+		// restore the outer scope even when the block bailed out early
+		self.environment = previous;
+		result
+	}
+
+	fn evaluate(&mut self, expr: &Expression) -> Result<Value, LoxError> {
+		match *expr {
+			Expression::Number(_) | Expression::Literal(_) | Expression::True | Expression::False | Expression::Nil => {
+				Ok(literal_value(expr))
 			},
-			TokenType::Minus => {
-				let expr_value = self.evaluate(expr)?;
-				if let Value::Number(n) = expr_value {
-					return Ok(Value::Number(-n));
-				} else {
-					return Err("attempted negation on non-number");
+			Expression::Unary(ref tt, ref be) => self.evaluate_unary(tt, be),
+			Expression::Binary(ref bel, ref tt, ref ber) => self.evaluate_binary(bel, tt, ber),
+			Expression::Logical(ref bel, ref tt, ref ber) => self.evaluate_logical(bel, tt, ber),
+			Expression::Grouping(ref be) => self.evaluate(be),
+	        Expression::Variable(ref var_name, depth) => self.var_lookup(var_name, depth),
+	        Expression::Assign(ref var_name, ref be, depth) => self.evaluate_assign(var_name, be, depth),
+	        Expression::Call(ref callee, ref paren, ref arguments) => self.evaluate_call(callee, paren, arguments),
+	        Expression::Get(ref object, ref prop_name) => self.evaluate_get(object, prop_name),
+	        Expression::Set(ref object, ref prop_name, ref be) => self.evaluate_set(object, prop_name, be),
+	        Expression::This(ref keyword, depth) => self.var_lookup(keyword, depth),
+	        Expression::Super(ref keyword, ref method_name, depth) => self.evaluate_super(keyword, method_name, depth),
+		}
+	}
+
+	fn evaluate_assign(&mut self, var_name: &Token, expr: &Expression, depth: Option<usize>) -> Result<Value, LoxError> {
+		let var_val = self.evaluate(expr)?;
+		self.assign(var_name, var_val, depth)
+	}
+
+	fn assign(&mut self, var_name: &Token, var_val: Value, depth: Option<usize>) -> Result<Value, LoxError> {
+		match depth {
+			Some(distance) => Environment::assign_at(&self.environment, distance, var_name.symbol().clone(), var_val.clone()),
+			None => self.globals.borrow_mut().assign(var_name, var_val.clone())?,
+		}
+		return Ok(var_val);
+	}
+
+	fn evaluate_call(&mut self, callee: &Expression, paren: &Token, arguments: &[Expression]) -> Result<Value, LoxError> {
+		let (callee_val, argument_vals) = self.evaluate_operands(callee, arguments)?;
+		match callee_val {
+			Value::Function(function) => self.call_function(&function, argument_vals, paren),
+			Value::NativeFunction(native) => self.call_native(&native, &argument_vals, paren),
+			Value::Class(class) => self.instantiate(class, argument_vals, paren),
+			_ => Err(LoxError::runtime(paren, "Can only call functions and classes.")),
+		}
+	}
+
+	// the callee, then the arguments in order
+	fn evaluate_operands(&mut self, callee: &Expression, arguments: &[Expression]) -> Result<(Value, Vec<Value>), LoxError> {
+		let callee_val = self.evaluate(callee)?;
+		let mut argument_vals = vec![];
+		for argument in arguments {
+			argument_vals.push(self.evaluate(argument)?);
+		}
+		return Ok((callee_val, argument_vals));
+	}
+
+	fn call_native(&mut self, native: &NativeFunction, arguments: &[Value], paren: &Token) -> Result<Value, LoxError> {
+		check_arity(paren, native.arity(), arguments.len())?;
+		let result = (native.function)(arguments).map_err(|message| LoxError::runtime(paren, &message))?;
+		return Ok(self.intern_value(result));
+	}
+
+	fn evaluate_get(&mut self, object: &Expression, prop_name: &Token) -> Result<Value, LoxError> {
+		let instance = self.evaluate_instance(object, prop_name, "Only instances have properties.")?;
+		return self.instance_get(instance, prop_name);
+	}
+
+	fn evaluate_set(&mut self, object: &Expression, prop_name: &Token, expr: &Expression) -> Result<Value, LoxError> {
+		let instance = self.evaluate_instance(object, prop_name, "Only instances have fields.")?;
+		let prop_val = self.evaluate(expr)?;
+		instance.borrow_mut().fields.insert(prop_name.symbol().clone(), prop_val.clone());
+		return Ok(prop_val);
+	}
+
+	// the object of a property access, which must be an instance
+	fn evaluate_instance(&mut self, object: &Expression, prop_name: &Token, message: &str) -> Result<Rc<RefCell<LoxInstance>>, LoxError> {
+		if let Value::Instance(instance) = self.evaluate(object)? {
+			return Ok(instance);
+		}
+		return Err(LoxError::runtime(prop_name, message));
+	}
+
+	fn evaluate_super(&mut self, keyword: &Token, method_name: &Token, depth: Option<usize>) -> Result<Value, LoxError> {
+		// `this` is bound in the scope just inside the one holding `super`
+		if let Some(distance) = depth {
+			let superclass = Environment::get_at(&self.environment, distance, &self.super_symbol);
+			let object = Environment::get_at(&self.environment, distance - 1, &self.this_symbol);
+			if let (Some(Value::Class(superclass)), Some(Value::Instance(instance))) = (superclass, object) {
+				if let Some(method) = superclass.find_method(method_name.symbol()) {
+					return Ok(Value::Function(Rc::new(self.bind(&method, instance))));
 				}
+				return Err(undefined_property(method_name));
 			}
-			_ => { return Err("unrecognized unary operator"); }
 		}
+		return Err(LoxError::runtime(keyword, "Can't use 'super' outside of a subclass method."));
+	}
+
+	fn instantiate(&mut self, class: Rc<LoxClass>, arguments: Vec<Value>, paren: &Token) -> Result<Value, LoxError> {
+		check_arity(paren, class.arity(), arguments.len())?;
+		let instance = self.new_instance(class.clone());
+		if let Some(ref initializer) = class.initializer {
+			let initializer = self.bind(initializer, instance.clone());
+			self.call_function(&initializer, arguments, paren)?;
+		}
+		return Ok(Value::Instance(instance));
+	}
+
+	fn call_function(&mut self, function: &LoxFunction, arguments: Vec<Value>, paren: &Token) -> Result<Value, LoxError> {
+		let call_env = self.enter_call(function, arguments, paren)?;
+		let result = self.execute_block(&function.declaration.body, call_env);
+		self.leave_call(function, result)
+	}
+
+	// the parts of a call that happen before and after its body runs are
+	// kept out of `call_function`, whose frame stays on the Rust stack for
+	// as long as the body does
+	fn enter_call(&mut self, function: &LoxFunction, arguments: Vec<Value>, paren: &Token) -> Result<Rc<RefCell<Environment>>, LoxError> {
+		check_arity(paren, function.arity(), arguments.len())?;
+		if self.frames.len() >= self.max_call_depth {
+			return Err(LoxError::runtime(paren, "Stack overflow."));
+		}
+
+		let mut call_env = Environment::with_enclosing(function.closure.clone());
+		for (param, argument) in function.declaration.params.iter().zip(arguments) {
+			call_env.define(param.symbol().clone(), argument);
+		}
+
+		self.frames.push(CallFrame { function: function.declaration.name.symbol().clone(), call_line: paren.line() });
+		return Ok(self.new_environment(call_env));
+	}
+
+	fn leave_call(&mut self, function: &LoxFunction, result: Result<Option<Value>, LoxError>) -> Result<Value, LoxError> {
+		let result = result.map_err(|e| self.with_trace(e));
+		self.frames.pop();
+		let return_val = result?;
+
+		// initializers always hand back the instance, even on an early `return;`
+		if function.is_initializer {
+			return Ok(Environment::get_at(&function.closure, 0, &self.this_symbol).unwrap_or(Value::Nil));
+		}
+		return Ok(return_val.unwrap_or(Value::Nil));
+	}
+
+	fn var_lookup(&self, var_name: &Token, depth: Option<usize>) -> Result<Value, LoxError> {
+		match depth {
+			Some(distance) => {
+				let var_val = Environment::get_at(&self.environment, distance, var_name.symbol());
+				var_val.ok_or_else(|| LoxError::runtime(var_name, &format!("Undefined variable '{}'.", var_name.lexeme())))
+			},
+			None => self.globals.borrow().get(var_name),
+		}
+	}
+
+	fn evaluate_unary(&mut self, operator: &Token, expr: &Expression) -> Result<Value, LoxError> {
+		let expr_value = self.evaluate(expr)?;
+		unary_op(operator, expr_value)
+	}
+
+	// the right operand is only evaluated when the left one doesn't
+	// already decide the result, and the deciding operand is returned as-is
+	fn evaluate_logical(&mut self, expr_l: &Expression, operator: &Token, expr_r: &Expression) -> Result<Value, LoxError> {
+		let val_l = self.evaluate(expr_l)?;
+		match operator.token_type() {
+			TokenType::Or => { if is_truthy(val_l.clone()) { return Ok(val_l); } },
+			TokenType::And => { if !is_truthy(val_l.clone()) { return Ok(val_l); } },
+			_ => { return Err(LoxError::runtime(operator, "Unrecognized logical operator.")); },
+		}
+		return self.evaluate(expr_r);
 	}
 
-	fn evaluate_binary(&mut self, expr_l: Expression, operator: TokenType, expr_r: Expression) -> Result<Value, &'static str> {
+	fn evaluate_binary(&mut self, expr_l: &Expression, operator: &Token, expr_r: &Expression) -> Result<Value, LoxError> {
 		let val_l = self.evaluate(expr_l)?;
 		let val_r = self.evaluate(expr_r)?;
-		match operator {
+		self.binary_op(operator, val_l, val_r)
+	}
+
+	fn binary_op(&mut self, operator: &Token, val_l: Value, val_r: Value) -> Result<Value, LoxError> {
+		match operator.token_type() {
 			TokenType::EqualEqual => Ok(Value::Boolean(val_l == val_r)),
 			TokenType::BangEqual => Ok(Value::Boolean(val_l != val_r)),
-			TokenType::Less => {return number_comp(val_l, val_r, |x, y| x < y); }
-			TokenType::LessEqual => {return number_comp(val_l, val_r, |x, y| x <= y); }
-			TokenType::Greater => {return number_comp(val_l, val_r, |x, y| x > y); }
-			TokenType::GreaterEqual => {return number_comp(val_l, val_r, |x, y| x >= y); }
-			TokenType::Plus => {return arith_op(val_l, val_r, |x, y| x + y); }
-			TokenType::Minus => {return arith_op(val_l, val_r, |x, y| x - y); }
-			TokenType::Star => { return arith_op(val_l, val_r, |x, y| x * y); }
-			TokenType::Slash => { return arith_op(val_l, val_r, |x, y| x / y); }
-			_ => Err("unrecognized binary operator"),
+			TokenType::Less => {return number_comp(operator, val_l, val_r, |x, y| x < y); }
+			TokenType::LessEqual => {return number_comp(operator, val_l, val_r, |x, y| x <= y); }
+			TokenType::Greater => {return number_comp(operator, val_l, val_r, |x, y| x > y); }
+			TokenType::GreaterEqual => {return number_comp(operator, val_l, val_r, |x, y| x >= y); }
+			TokenType::Plus => {
+				match (val_l, val_r) {
+					(Value::Number(n_l), Value::Number(n_r)) => Ok(Value::Number(n_l + n_r)),
+					(Value::StringLiteral(s_l), Value::StringLiteral(s_r)) => {
+						Ok(Value::StringLiteral(self.interner.intern(&(s_l.to_string() + &s_r))))
+					},
+					_ => Err(LoxError::runtime(operator, "Operands must be two numbers or two strings.")),
+				}
+			}
+			TokenType::Minus => {return arith_op(operator, val_l, val_r, |x, y| x - y); }
+			TokenType::Star => { return arith_op(operator, val_l, val_r, |x, y| x * y); }
+			TokenType::Slash => { return arith_op(operator, val_l, val_r, |x, y| x / y); }
+			_ => Err(LoxError::runtime(operator, "Unrecognized binary operator.")),
+		}
+	}
+}
+
+impl Default for Interpreter {
+	fn default() -> Self {
+		Interpreter::new()
+	}
+}
+
+fn check_arity(paren: &Token, arity: usize, arg_count: usize) -> Result<(), LoxError> {
+	if arg_count != arity {
+		let message = format!("Expected {} arguments but got {}.", arity, arg_count);
+		return Err(LoxError::runtime(paren, &message));
+	}
+	Ok(())
+}
+
+fn literal_value(expr: &Expression) -> Value {
+	match *expr {
+		Expression::Number(n) => Value::Number(n),
+		Expression::Literal(ref s) => Value::StringLiteral(s.clone()),
+		Expression::True => Value::Boolean(true),
+		Expression::False => Value::Boolean(false),
+		_ => Value::Nil,
+	}
+}
+
+fn unary_op(operator: &Token, expr_value: Value) -> Result<Value, LoxError> {
+	match operator.token_type() {
+		TokenType::Bang => {
+			return Ok(Value::Boolean(!is_truthy(expr_value)));
+		},
+		TokenType::Minus => {
+			if let Value::Number(n) = expr_value {
+				return Ok(Value::Number(-n));
+			} else {
+				return Err(LoxError::runtime(operator, "Operand must be a number."));
+			}
 		}
+		_ => { return Err(LoxError::runtime(operator, "Unrecognized unary operator.")); }
 	}
 }
 
-fn arith_op<F>(val_r: Value, val_l: Value, op: F) -> Result<Value, &'static str> 
+fn arith_op<F>(operator: &Token, val_l: Value, val_r: Value, op: F) -> Result<Value, LoxError> 
 	where F: Fn(f64, f64) -> f64 {
 	if let Value::Number(n_l) = val_l {
 		if let Value::Number(n_r) = val_r {
 			return Ok(Value::Number(op(n_l, n_r)));
 		}
 	}
-	return Err("attempted arithmetic with non-number");
+	return Err(LoxError::runtime(operator, "Operands must be numbers."));
 }
 
-fn number_comp<F>(val_r: Value, val_l: Value, op: F) -> Result<Value, &'static str> 
+fn number_comp<F>(operator: &Token, val_l: Value, val_r: Value, op: F) -> Result<Value, LoxError> 
 	where F: Fn(f64, f64) -> bool {
 	if let Value::Number(n_l) = val_l {
 		if let Value::Number(n_r) = val_r {
 			return Ok(Value::Boolean(op(n_l, n_r)));
 		}
 	}
-	return Err("attempted comparison with non-number");
+	return Err(LoxError::runtime(operator, "Operands must be numbers."));
 }
 
 fn is_truthy(val: Value) -> bool {
@@ -0,0 +1,239 @@
+use environment::Environment;
+use interpret::{LoxClass, LoxFunction, LoxInstance, Value};
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::rc::{Rc, Weak};
+
+// collect once this many scopes and instances have been made, then whenever
+// their number has grown by GROW_FACTOR since the last collection
+const INITIAL_THRESHOLD: usize = 1024;
+const GROW_FACTOR: usize = 2;
+
+// Reference counting frees most of what the tree-walker allocates, but not
+// cycles: a function stored in the scope it closes over, or an instance
+// holding itself in a field. Every scope and instance is registered here,
+// and once enough have piled up the cycles among them are found by trial
+// deletion: whatever is only referred to from inside the graph of scopes,
+// instances, functions and classes is unreachable, and is cleared.
+pub struct Cycles {
+	environments: Vec<Weak<RefCell<Environment>>>,
+	instances: Vec<Weak<RefCell<LoxInstance>>>,
+	next_collection: usize,
+}
+
+impl Cycles {
+	pub fn new() -> Self {
+		Cycles { environments: vec![], instances: vec![], next_collection: INITIAL_THRESHOLD }
+	}
+
+	pub fn track_environment(&mut self, environment: &Rc<RefCell<Environment>>) {
+		self.environments.push(Rc::downgrade(environment));
+	}
+
+	pub fn track_instance(&mut self, instance: &Rc<RefCell<LoxInstance>>) {
+		self.instances.push(Rc::downgrade(instance));
+	}
+
+	pub fn should_collect(&self) -> bool {
+		self.environments.len() + self.instances.len() > self.next_collection
+	}
+
+	pub fn live_objects(&self) -> usize {
+		let environments = self.environments.iter().filter(|e| e.strong_count() > 0).count();
+		environments + self.instances.iter().filter(|i| i.strong_count() > 0).count()
+	}
+
+	// must not run while any scope or instance is borrowed mutably; one
+	// that is borrowed at all is kept, along with everything it refers to
+	pub fn collect(&mut self) {
+		self.environments.retain(|e| e.strong_count() > 0);
+		self.instances.retain(|i| i.strong_count() > 0);
+
+		let mut graph = Graph { nodes: HashMap::new(), pending: vec![] };
+		for environment in self.environments.iter().filter_map(Weak::upgrade) {
+			graph.add(Node::Environment(environment));
+		}
+		for instance in self.instances.iter().filter_map(Weak::upgrade) {
+			graph.add(Node::Instance(instance));
+		}
+		graph.explore();
+		let garbage = graph.unreachable();
+
+		// dropped only once nothing is borrowed, since dropping them may
+		// free other scopes and instances
+		let mut environments = vec![];
+		let mut fields = vec![];
+		for node in &garbage {
+			match *node {
+				Node::Environment(ref environment) => environments.push(mem::take(&mut *environment.borrow_mut())),
+				Node::Instance(ref instance) => fields.push(mem::take(&mut instance.borrow_mut().fields)),
+				Node::Function(_) | Node::Class(_) => {},
+			}
+		}
+		drop(graph);
+		drop(garbage);
+		drop(environments);
+		drop(fields);
+
+		self.environments.retain(|e| e.strong_count() > 0);
+		self.instances.retain(|i| i.strong_count() > 0);
+		let tracked = self.environments.len() + self.instances.len();
+		self.next_collection = (tracked * GROW_FACTOR).max(INITIAL_THRESHOLD);
+	}
+}
+
+impl Default for Cycles {
+	fn default() -> Self {
+		Cycles::new()
+	}
+}
+
+// Scopes and instances are the only things a cycle can be closed through,
+// since functions and classes never change once made, so clearing them is
+// enough to break every cycle.
+#[derive(Clone)]
+enum Node {
+	Environment(Rc<RefCell<Environment>>),
+	Instance(Rc<RefCell<LoxInstance>>),
+	Function(Rc<LoxFunction>),
+	Class(Rc<LoxClass>),
+}
+
+impl Node {
+	// identifies the allocation, whatever kind of node it is
+	fn key(&self) -> usize {
+		match *self {
+			Node::Environment(ref e) => Rc::as_ptr(e) as *const u8 as usize,
+			Node::Instance(ref i) => Rc::as_ptr(i) as *const u8 as usize,
+			Node::Function(ref f) => Rc::as_ptr(f) as *const u8 as usize,
+			Node::Class(ref c) => Rc::as_ptr(c) as *const u8 as usize,
+		}
+	}
+
+	fn strong_count(&self) -> usize {
+		match *self {
+			Node::Environment(ref e) => Rc::strong_count(e),
+			Node::Instance(ref i) => Rc::strong_count(i),
+			Node::Function(ref f) => Rc::strong_count(f),
+			Node::Class(ref c) => Rc::strong_count(c),
+		}
+	}
+
+	// every reference this node holds to another node, once per reference;
+	// None if the node is borrowed and can't be looked into
+	fn children(&self) -> Option<Vec<Node>> {
+		let mut children = vec![];
+		match *self {
+			Node::Environment(ref environment) => {
+				let environment = environment.try_borrow().ok()?;
+				children.extend(environment.values().filter_map(value_node));
+				if let Some(enclosing) = environment.enclosing() {
+					children.push(Node::Environment(enclosing.clone()));
+				}
+			},
+			Node::Instance(ref instance) => {
+				let instance = instance.try_borrow().ok()?;
+				children.push(Node::Class(instance.class.clone()));
+				children.extend(instance.fields.values().filter_map(value_node));
+			},
+			Node::Function(ref function) => {
+				children.push(Node::Environment(function.closure.clone()));
+			},
+			Node::Class(ref class) => {
+				if let Some(ref superclass) = class.superclass {
+					children.push(Node::Class(superclass.clone()));
+				}
+				children.extend(class.methods.values().map(|m| Node::Function(m.clone())));
+				if let Some(ref initializer) = class.initializer {
+					children.push(Node::Function(initializer.clone()));
+				}
+			},
+		}
+		Some(children)
+	}
+}
+
+fn value_node(value: &Value) -> Option<Node> {
+	match *value {
+		Value::Function(ref function) => Some(Node::Function(function.clone())),
+		Value::Class(ref class) => Some(Node::Class(class.clone())),
+		Value::Instance(ref instance) => Some(Node::Instance(instance.clone())),
+		_ => None,
+	}
+}
+
+struct GraphNode {
+	node: Node,
+	children: Vec<usize>,
+	// references from outside the graph: the interpreter, the Rust stack,
+	// the host; None if the node couldn't be looked into
+	external: Option<usize>,
+}
+
+struct Graph {
+	nodes: HashMap<usize, GraphNode>,
+	// added but not yet explored
+	pending: Vec<usize>,
+}
+
+impl Graph {
+	fn add(&mut self, node: Node) -> usize {
+		let key = node.key();
+		if let Entry::Vacant(entry) = self.nodes.entry(key) {
+			entry.insert(GraphNode { node: node, children: vec![], external: Some(0) });
+			self.pending.push(key);
+		}
+		key
+	}
+
+	// finds everything reachable from the tracked nodes, holding one
+	// reference to each, and the references between them
+	fn explore(&mut self) {
+		while let Some(key) = self.pending.pop() {
+			let children = self.nodes[&key].node.children();
+			match children {
+				Some(children) => {
+					let keys = children.into_iter().map(|child| self.add(child)).collect();
+					self.nodes.get_mut(&key).unwrap().children = keys;
+				},
+				None => { self.nodes.get_mut(&key).unwrap().external = None; },
+			}
+		}
+
+		// every count now includes the graph's own reference; take that
+		// and each reference from another node away, and what's left came
+		// from outside
+		let mut internal: HashMap<usize, usize> = HashMap::new();
+		for graph_node in self.nodes.values() {
+			for child in &graph_node.children {
+				*internal.entry(*child).or_insert(0) += 1;
+			}
+		}
+		for (key, graph_node) in self.nodes.iter_mut() {
+			if graph_node.external.is_some() {
+				let internal = internal.get(key).cloned().unwrap_or(0);
+				graph_node.external = Some(graph_node.node.strong_count() - 1 - internal);
+			}
+		}
+	}
+
+	// the nodes not reachable from any node referred to from outside
+	fn unreachable(&self) -> Vec<Node> {
+		let mut reached = HashSet::new();
+		let mut stack: Vec<usize> = self.nodes.iter()
+			.filter(|&(_, n)| n.external != Some(0))
+			.map(|(key, _)| *key)
+			.collect();
+		while let Some(key) = stack.pop() {
+			if reached.insert(key) {
+				stack.extend(self.nodes[&key].children.iter().cloned());
+			}
+		}
+		self.nodes.iter()
+			.filter(|&(key, _)| !reached.contains(key))
+			.map(|(_, n)| n.node.clone())
+			.collect()
+	}
+}
@@ -0,0 +1,163 @@
+//! Interpreters for the Lox language from *Crafting Interpreters*.
+//!
+//! The quickest way to run a script is through [`Lox`], which keeps global state
+//! between calls:
+//!
+//! ```
+//! let mut lox = rustlox::Lox::new();
+//! lox.run_source("var greeting = \"hello\";").unwrap();
+//! let value = lox.run_source("greeting + \" world\";").unwrap();
+//! assert_eq!(value.to_string(), "hello world");
+//! ```
+//!
+//! The individual stages ([`Scanner`], [`Parser`], [`Resolver`], [`Interpreter`])
+//! are public as well, for hosts that want to drive them separately, along
+//! with [`Optimizer`], an optional constant-folding pass over resolved programs.
+//! [`printer`] renders a tree back to Lox source, or to the book's prefix form.
+//!
+//! [`Vm`] is a second backend in the style of clox: it compiles a program
+//! from [`parse_program`] to bytecode and runs it on a stack machine.
+
+extern crate unicode_xid;
+
+pub mod error;
+pub mod host;
+pub mod intern;
+// the original scanner, parser and tree-walker are written with explicit
+// returns and `name: name` fields, which clippy would rather they weren't
+#[allow(clippy::needless_return, clippy::redundant_field_names, clippy::manual_range_contains)]
+pub mod scanner;
+#[allow(clippy::needless_return, clippy::redundant_field_names, clippy::init_numbered_fields)]
+pub mod parse;
+pub mod resolve;
+pub mod optimize;
+pub mod printer;
+pub mod environment;
+#[allow(clippy::needless_return, clippy::redundant_field_names, clippy::match_like_matches_macro)]
+pub mod interpret;
+pub mod object;
+pub mod heap;
+pub mod chunk;
+pub mod compiler;
+pub mod debug;
+pub mod vm;
+
+use std::io::Write;
+
+pub use error::{ErrorKind, LoxError};
+pub use intern::{Interner, Symbol};
+pub use interpret::{Interpreter, Value};
+pub use optimize::Optimizer;
+pub use parse::{Parser, Statement};
+pub use resolve::Resolver;
+pub use scanner::Scanner;
+pub use vm::Vm;
+
+pub struct Lox {
+	// need to be able to maintain intepreter state
+	// between runs, e.g. lines in a REPL
+	interpreter: Interpreter,
+	optimize: bool,
+}
+
+impl Lox {
+	pub fn new() -> Self {
+		Lox { interpreter: Interpreter::new(), optimize: false }
+	}
+
+	/// Creates an interpreter whose `print` statements write to `output`
+	/// instead of stdout.
+	///
+	/// ```
+	/// use std::cell::RefCell;
+	/// use std::io::{self, Write};
+	/// use std::rc::Rc;
+	///
+	/// #[derive(Clone, Default)]
+	/// struct Captured(Rc<RefCell<Vec<u8>>>);
+	///
+	/// impl Write for Captured {
+	///     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+	///         self.0.borrow_mut().write(buf)
+	///     }
+	///     fn flush(&mut self) -> io::Result<()> { Ok(()) }
+	/// }
+	///
+	/// let captured = Captured::default();
+	/// let mut lox = rustlox::Lox::with_output(Box::new(captured.clone()));
+	/// lox.run_source("print 1 + 2; print \"done\";").unwrap();
+	/// assert_eq!(&*captured.0.borrow(), b"3\ndone\n");
+	/// ```
+	pub fn with_output(output: Box<dyn Write>) -> Self {
+		Lox { interpreter: Interpreter::with_output(output), optimize: false }
+	}
+
+	/// Registers a Rust closure as a global Lox function taking `arity`
+	/// arguments. Returning `Err(message)` raises a Lox runtime error.
+	///
+	/// ```
+	/// use rustlox::{Lox, Value};
+	///
+	/// let mut lox = Lox::new();
+	/// lox.define_native("double", 1, |args| match args[0] {
+	///     Value::Number(n) => Ok(Value::Number(n * 2.0)),
+	///     _ => Err("Argument must be a number.".to_string()),
+	/// });
+	/// assert_eq!(lox.run_source("double(21);").unwrap(), Value::Number(42.0));
+	/// assert!(lox.run_source("double(\"x\");").is_err());
+	/// ```
+	pub fn define_native<F>(&mut self, name: &str, arity: usize, function: F)
+		where F: Fn(&[Value]) -> Result<Value, String> + 'static {
+		self.interpreter.define_native(name, arity, function);
+	}
+
+	/// Runs programs through the [`Optimizer`] before interpreting them.
+	/// Off by default; output and errors are the same either way.
+	pub fn set_optimize(&mut self, optimize: bool) {
+		self.optimize = optimize;
+	}
+
+	/// Sets how deep Lox calls may nest; see [`Interpreter::set_max_call_depth`].
+	pub fn set_max_call_depth(&mut self, depth: usize) {
+		self.interpreter.set_max_call_depth(depth);
+	}
+
+	/// Runs a piece of Lox source, returning the value of its final statement
+	/// if that is an expression statement, or `nil` otherwise. Only the first
+	/// error is returned; use [`Lox::run`] to get every static error at once.
+	pub fn run_source(&mut self, source: &str) -> Result<Value, LoxError> {
+		self.run(source).map_err(|mut errors| errors.remove(0))
+	}
+
+	/// Like [`Lox::run_source`], but reports every lexical, syntax or
+	/// resolution error found instead of just the first.
+	pub fn run(&mut self, source: &str) -> Result<Value, Vec<LoxError>> {
+		let mut program = parse_program(source, self.interpreter.interner())?;
+		if self.optimize {
+			Optimizer::new(self.interpreter.interner()).optimize(&mut program);
+		}
+		self.interpreter.interpret(&program).map_err(|e| vec![e])
+	}
+}
+
+/// Scans, parses and resolves a piece of Lox source, producing a program
+/// either backend can run. Every static error found is reported.
+/// Names are interned in `interner`, which for an [`Interpreter`] must be
+/// its own; the VM interns strings separately, so any table will do.
+pub fn parse_program(source: &str, interner: &Interner) -> Result<Vec<Statement>, Vec<LoxError>> {
+	let this_scanner = Scanner::new(source, interner);
+	let scanned_tokens = this_scanner.scan_tokens()?;
+
+	let mut this_parser = Parser::new(scanned_tokens);
+	let mut program = this_parser.parse()?;
+
+	let mut this_resolver = Resolver::new();
+	this_resolver.resolve(&mut program)?;
+	Ok(program)
+}
+
+impl Default for Lox {
+	fn default() -> Self {
+		Lox::new()
+	}
+}
@@ -1,47 +1,56 @@
+extern crate rustlox;
+
+use rustlox::{parse_program, printer, Interner, Lox, Optimizer, Vm};
 use std::env;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
-mod scanner;
-mod parse;
-mod interpret;
+enum Backend {
+	TreeWalker(Lox),
+	Bytecode(Vm),
+}
+
+// how to show each program's tree before running it
+#[derive(Clone, Copy)]
+enum TreeFormat {
+	Source,
+	Lisp,
+}
 
 struct RustLox {
-	// need to be able to maintain intepreter state
-	// between lines in REPL
-	interpreter: interpret::Interpreter,
+	backend: Backend,
+	optimize: bool,
+	print_tree: Option<TreeFormat>,
 }
 
 impl RustLox {
-	pub fn new() -> Self {
-		RustLox { interpreter: interpret::Interpreter::new() }
+	pub fn new(backend: Backend, optimize: bool, print_tree: Option<TreeFormat>) -> Self {
+		RustLox { backend, optimize, print_tree }
 	}
 
 	fn run(&mut self, lines: &str) {
-		let this_scanner = scanner::Scanner::new(lines);
-		// println!("Should run: \n{}", lines);
-		let scanned_tokens = this_scanner.scan_tokens();
-		// for token in &scanned_tokens {
-		// 	println!("{}", token);
-		// }
-		let mut this_parser = parse::Parser::new(scanned_tokens);
-		let this_parse = this_parser.parse();
-		if let Err(e) = this_parse {
-			println!("Parse error: {:?}", e);
-			return;
+		if let Some(format) = self.print_tree {
+			print_tree(lines, self.optimize, format);
 		}
-		// {
-		// 	let parsed = this_parse.unwrap();
-		// 	println!("\nParses to:\n{:?}", parsed);
-		// }
-
-		let this_result = self.interpreter.interpret(this_parse.unwrap());
-		if let Err(e) = this_result {
-			println!("Runtime error: {:?}", e);
-			return;
+		let optimize = self.optimize;
+		let result = match self.backend {
+			Backend::TreeWalker(ref mut lox) => lox.run(lines).map(|_| ()),
+			Backend::Bytecode(ref mut vm) => {
+				let interner = Interner::new();
+				parse_program(lines, &interner).and_then(|mut program| {
+					if optimize {
+						Optimizer::new(&interner).optimize(&mut program);
+					}
+					vm.interpret(&program).map_err(|e| vec![e])
+				})
+			},
+		};
+		if let Err(errors) = result {
+			for e in errors {
+				eprintln!("{}", e);
+			}
 		}
-		// println!("\nInterprets to: {:?}", this_result.unwrap());
 	}
 
 	fn run_prompt(&mut self) {
@@ -68,14 +77,65 @@ impl RustLox {
 	}
 }
 
+// prints the tree a program will run as; any errors are left for the
+// run that follows to report
+fn print_tree(lines: &str, optimize: bool, format: TreeFormat) {
+	let interner = Interner::new();
+	if let Ok(mut program) = parse_program(lines, &interner) {
+		if optimize {
+			Optimizer::new(&interner).optimize(&mut program);
+		}
+		match format {
+			TreeFormat::Source => print!("{}", printer::print_program(&program)),
+			TreeFormat::Lisp => print!("{}", printer::lisp_program(&program)),
+		}
+	}
+}
+
+// removes every occurrence of the flag, returning whether there was one
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+	let before = args.len();
+	args.retain(|arg| arg != flag);
+	args.len() != before
+}
+
 pub fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().skip(1).collect();
+
+    // --vm selects the bytecode backend over the tree-walker;
+    // its debugging flags imply it
+    let use_vm = take_flag(&mut args, "--vm");
+    let disassemble = take_flag(&mut args, "--disassemble");
+    let trace = take_flag(&mut args, "--trace");
+    let gc_stress = take_flag(&mut args, "--gc-stress");
+    // --print-ast and --print-lisp show the tree before running it, as Lox
+    // source or in prefix form; --show-optimized is --optimize --print-ast
+    let show_optimized = take_flag(&mut args, "--show-optimized");
+    let optimize = take_flag(&mut args, "--optimize") || show_optimized;
+    let mut print_tree = None;
+    if take_flag(&mut args, "--print-ast") || show_optimized {
+    	print_tree = Some(TreeFormat::Source);
+    }
+    if take_flag(&mut args, "--print-lisp") {
+    	print_tree = Some(TreeFormat::Lisp);
+    }
+
+    let mut lox = Lox::new();
+    lox.set_optimize(optimize);
+    let mut backend = Backend::TreeWalker(lox);
+    if use_vm || disassemble || trace || gc_stress {
+    	let mut vm = Vm::new();
+    	vm.set_disassemble(disassemble);
+    	vm.set_trace(trace);
+    	vm.set_gc_stress(gc_stress);
+    	backend = Backend::Bytecode(vm);
+    }
 
-    let mut this_rustlox = RustLox::new();
+    let mut this_rustlox = RustLox::new(backend, optimize, print_tree);
 
     match args.len() {
-    	1 => { this_rustlox.run_prompt(); },
-    	2 => { this_rustlox.run_file(&args[1]); },
-    	_ => { println!("Usage: rustlox [script]"); },
+    	0 => { this_rustlox.run_prompt(); },
+    	1 => { this_rustlox.run_file(&args[0]); },
+    	_ => { println!("Usage: rustlox [--vm] [--disassemble] [--trace] [--gc-stress] [--optimize] [--show-optimized] [--print-ast] [--print-lisp] [script]"); },
     }
 }
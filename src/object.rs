@@ -0,0 +1,82 @@
+use chunk::Chunk;
+use heap::ObjRef;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// Values and heap objects for the bytecode VM. Objects live in the
+// garbage-collected `Heap` and are referred to by handle, so values stay
+// small and `Copy`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+	Nil,
+	Boolean(bool),
+	Number(f64),
+	Obj(ObjRef),
+}
+
+pub enum Obj {
+	// interned: one object per distinct string, so handles compare by contents
+	String(Rc<str>),
+	Function(Function),
+	Native(Native),
+	Closure(Closure),
+	Upvalue(Upvalue),
+	Class(Class),
+	Instance(Instance),
+	BoundMethod(BoundMethod),
+}
+
+pub struct Function {
+	pub name: String,
+	pub arity: usize,
+	pub upvalue_count: usize,
+	// shared so a call frame can hold the code without borrowing the heap
+	pub chunk: Rc<Chunk>,
+}
+
+impl fmt::Display for Function {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		// only the top-level script has no name
+		if self.name.is_empty() {
+			return write!(f, "<script>");
+		}
+		write!(f, "<fn {}>", self.name)
+	}
+}
+
+pub type NativeFn = fn(&[Value]) -> Result<Value, String>;
+
+pub struct Native {
+	pub name: String,
+	pub arity: usize,
+	pub function: NativeFn,
+}
+
+pub struct Closure {
+	pub function: ObjRef,
+	pub upvalues: Vec<ObjRef>,
+}
+
+// a captured variable: still on the VM stack while open, moved into the
+// upvalue itself once its scope ends
+pub enum Upvalue {
+	Open(usize),
+	Closed(Value),
+}
+
+// methods and fields are keyed by their interned name
+pub struct Class {
+	pub name: ObjRef,
+	pub methods: HashMap<ObjRef, ObjRef>,
+}
+
+pub struct Instance {
+	pub class: ObjRef,
+	pub fields: HashMap<ObjRef, Value>,
+}
+
+pub struct BoundMethod {
+	pub receiver: Value,
+	pub method: ObjRef,
+}
@@ -0,0 +1,197 @@
+use intern::Interner;
+use parse::{declaration_mut, Expression, Statement};
+use scanner::TokenType;
+use std::mem;
+
+// An optional pass over a resolved program: folds operations on constants,
+// drops redundant groupings and prunes branches a constant condition rules
+// out. Anything that would fail at runtime, like `1 + "a"`, is left alone,
+// so errors are still raised where and when they were before.
+pub struct Optimizer {
+	// folded strings are interned where the program's literals were,
+	// so they still compare equal to them
+	interner: Interner,
+}
+
+impl Optimizer {
+	pub fn new(interner: &Interner) -> Self {
+		Optimizer { interner: interner.clone() }
+	}
+
+	pub fn optimize(&self, program: &mut [Statement]) {
+		for stmt in program.iter_mut() {
+			self.optimize_statement(stmt);
+		}
+	}
+
+	fn optimize_statement(&self, stmt: &mut Statement) {
+		let simplified = match *stmt {
+			Statement::ExprStmt(ref mut e) | Statement::PrintStmt(_, ref mut e) |
+			Statement::VarDecl(_, ref mut e) | Statement::Return(_, ref mut e) => {
+				self.optimize_expression(e);
+				None
+			},
+			Statement::Block(ref mut statements) => {
+				self.optimize(statements);
+				None
+			},
+			Statement::If(ref mut condition, ref mut then_branch, ref mut else_branch) => {
+				self.optimize_expression(condition);
+				self.optimize_statement(then_branch);
+				if let Some(ref mut else_branch) = *else_branch {
+					self.optimize_statement(else_branch);
+				}
+				// a branch keeps its own block, if it had one, so resolved
+				// scope depths inside it still hold
+				match truthiness(condition) {
+					Some(true) => Some(mem::replace(&mut **then_branch, empty_statement())),
+					Some(false) => Some(else_branch.take().map_or_else(empty_statement, |b| *b)),
+					None => None,
+				}
+			},
+			Statement::While(ref mut condition, ref mut body) => {
+				self.optimize_expression(condition);
+				self.optimize_statement(body);
+				match truthiness(condition) {
+					Some(false) => Some(empty_statement()),
+					_ => None,
+				}
+			},
+			Statement::Function(ref mut declaration) => {
+				self.optimize(&mut declaration_mut(declaration).body);
+				None
+			},
+			Statement::Class(_, _, ref mut methods) => {
+				for method in methods.iter_mut() {
+					self.optimize(&mut declaration_mut(method).body);
+				}
+				None
+			},
+		};
+		if let Some(simplified) = simplified {
+			*stmt = simplified;
+		}
+	}
+
+	fn optimize_expression(&self, expr: &mut Expression) {
+		let folded = match *expr {
+			Expression::Grouping(ref mut inner) => {
+				self.optimize_expression(inner);
+				Some(take(inner))
+			},
+			Expression::Unary(ref operator, ref mut operand) => {
+				self.optimize_expression(operand);
+				fold_unary(operator.token_type(), operand)
+			},
+			Expression::Binary(ref mut left, ref operator, ref mut right) => {
+				self.optimize_expression(left);
+				self.optimize_expression(right);
+				self.fold_binary(left, operator.token_type(), right)
+			},
+			Expression::Logical(ref mut left, ref operator, ref mut right) => {
+				self.optimize_expression(left);
+				self.optimize_expression(right);
+				// a constant left operand decides which side is the result
+				match (truthiness(left), operator.token_type()) {
+					(Some(true), TokenType::Or) | (Some(false), TokenType::And) => Some(take(left)),
+					(Some(false), TokenType::Or) | (Some(true), TokenType::And) => Some(take(right)),
+					_ => None,
+				}
+			},
+			Expression::Assign(_, ref mut value, _) => {
+				self.optimize_expression(value);
+				None
+			},
+			Expression::Call(ref mut callee, _, ref mut arguments) => {
+				self.optimize_expression(callee);
+				for argument in arguments.iter_mut() {
+					self.optimize_expression(argument);
+				}
+				None
+			},
+			Expression::Get(ref mut object, _) => {
+				self.optimize_expression(object);
+				None
+			},
+			Expression::Set(ref mut object, _, ref mut value) => {
+				self.optimize_expression(object);
+				self.optimize_expression(value);
+				None
+			},
+			Expression::Number(_) | Expression::Literal(_) | Expression::True |
+			Expression::False | Expression::Nil | Expression::Variable(..) |
+			Expression::This(..) | Expression::Super(..) => None,
+		};
+		if let Some(folded) = folded {
+			*expr = folded;
+		}
+	}
+
+	fn fold_binary(&self, left: &Expression, operator: TokenType, right: &Expression) -> Option<Expression> {
+		match operator {
+			TokenType::EqualEqual => return constants_equal(left, right).map(boolean),
+			TokenType::BangEqual => return constants_equal(left, right).map(|equal| boolean(!equal)),
+			_ => {},
+		}
+		match (left, right) {
+			(&Expression::Number(l), &Expression::Number(r)) => match operator {
+				TokenType::Plus => Some(Expression::Number(l + r)),
+				TokenType::Minus => Some(Expression::Number(l - r)),
+				TokenType::Star => Some(Expression::Number(l * r)),
+				TokenType::Slash => Some(Expression::Number(l / r)),
+				TokenType::Greater => Some(boolean(l > r)),
+				TokenType::GreaterEqual => Some(boolean(l >= r)),
+				TokenType::Less => Some(boolean(l < r)),
+				TokenType::LessEqual => Some(boolean(l <= r)),
+				_ => None,
+			},
+			(Expression::Literal(l), Expression::Literal(r)) => match operator {
+				TokenType::Plus => Some(Expression::Literal(self.interner.intern(&(l.to_string() + r)))),
+				_ => None,
+			},
+			_ => None,
+		}
+	}
+}
+
+fn fold_unary(operator: TokenType, operand: &Expression) -> Option<Expression> {
+	match (operator, operand) {
+		(TokenType::Minus, &Expression::Number(n)) => Some(Expression::Number(-n)),
+		(TokenType::Bang, _) => truthiness(operand).map(|truthy| boolean(!truthy)),
+		_ => None,
+	}
+}
+
+// whether a constant is truthy, or None if it isn't a constant
+fn truthiness(expr: &Expression) -> Option<bool> {
+	match *expr {
+		Expression::Nil | Expression::False => Some(false),
+		Expression::True | Expression::Number(_) | Expression::Literal(_) => Some(true),
+		_ => None,
+	}
+}
+
+// equality between two constants, which never fails; constants of
+// different types are never equal
+fn constants_equal(left: &Expression, right: &Expression) -> Option<bool> {
+	if truthiness(left).is_none() || truthiness(right).is_none() {
+		return None;
+	}
+	match (left, right) {
+		(&Expression::Number(l), &Expression::Number(r)) => Some(l == r),
+		(Expression::Literal(l), Expression::Literal(r)) => Some(l.as_str() == r.as_str()),
+		_ => Some(left == right),
+	}
+}
+
+fn boolean(b: bool) -> Expression {
+	if b { Expression::True } else { Expression::False }
+}
+
+fn take(expr: &mut Expression) -> Expression {
+	mem::replace(expr, Expression::Nil)
+}
+
+fn empty_statement() -> Statement {
+	Statement::Block(vec![])
+}
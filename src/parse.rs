@@ -5,16 +5,34 @@ Expression Grammar
 
 program     → declaration* eof ;
 
-declaration → varDecl
+declaration → classDecl
+            | funDecl
+            | varDecl
             | statement ;
 
 statement   → exprStmt
-            | printStmt ;
-
+            | forStmt
+            | ifStmt
+            | printStmt
+            | returnStmt
+            | whileStmt
+            | block ;
+
+classDecl   → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
+funDecl     → "fun" function ;
+function    → IDENTIFIER "(" parameters? ")" block ;
+parameters  → IDENTIFIER ( "," IDENTIFIER )* ;
 varDecl     → "var" IDENTIFIER ( "=" expression )? ";" ;
 
 exprStmt    → expression ";" ;
+forStmt     → "for" "(" ( varDecl | exprStmt | ";" )
+                        expression? ";"
+                        expression? ")" statement ;
+ifStmt      → "if" "(" expression ")" statement ( "else" statement )? ;
 printStmt   → "print" expression ";" ;
+returnStmt  → "return" expression? ";" ;
+whileStmt   → "while" "(" expression ")" statement ;
+block       → "{" declaration* "}" ;
 
 expression → literal
            | unary
@@ -31,129 +49,369 @@ operator   → "==" | "!=" | "<" | "<=" | ">" | ">="
 Precedence Grammar
 ==================
 expression 	   → assignment ;
-assignment     → identifier "=" assignment
-               | equality ;
+assignment     → ( call "." )? identifier "=" assignment
+               | logic_or ;
+logic_or       → logic_and ( "or" logic_and )* ;
+logic_and      → equality ( "and" equality )* ;
 equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 comparison     → addition ( ( ">" | ">=" | "<" | "<=" ) addition )* ;
 addition       → multiplication ( ( "-" | "+" ) multiplication )* ;
 multiplication → unary ( ( "/" | "*" ) unary )* ;
 unary          → ( "!" | "-" ) unary ;
-               | primary ;
-primary        → NUMBER | STRING | "false" | "true" | "nil"
+               | call ;
+call           → primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
+arguments      → expression ( "," expression )* ;
+primary        → NUMBER | STRING | "false" | "true" | "nil" | "this"
                | "(" expression ")" 
-               | IDENTIFIER ;
+               | IDENTIFIER | "super" "." IDENTIFIER ;
 */
 
+use error::LoxError;
+use intern::Symbol;
 use scanner::{TokenType, Token};
+use std::rc::Rc;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Statement {
     ExprStmt(Expression),
-    PrintStmt(Expression),
-    VarDecl(String, Expression),
+    // the keyword locates a failure to write the output
+    PrintStmt(Token, Expression),
+    VarDecl(Token, Expression),
+    Block(Vec<Statement>),
+    If(Expression, Box<Statement>, Option<Box<Statement>>),
+    While(Expression, Box<Statement>),
+    // shared with the functions created from it, which outlive the tree
+    Function(Rc<FunctionDecl>),
+    Return(Token, Expression),
+    Class(Token, Option<Expression>, Vec<Rc<FunctionDecl>>),
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionDecl {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Statement>,
 }
 
-#[derive(Debug, PartialEq)]
+/// Gives passes that rewrite the tree, like the resolver, a declaration
+/// they can change. One still shared, with a copy of the program or with
+/// functions already made from it, is copied first, so the others keep
+/// seeing it as it was.
+pub fn declaration_mut(declaration: &mut Rc<FunctionDecl>) -> &mut FunctionDecl {
+    Rc::make_mut(declaration)
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub enum Expression {
 	Number(f64),
-	Literal(String),
+	Literal(Symbol),
 	True,
 	False,
 	Nil,
-	Unary(TokenType, Box<Expression>),
-	Binary(Box<Expression>, TokenType, Box<Expression>),
+	// tokens are kept wherever evaluation can fail, to locate the error
+	Unary(Token, Box<Expression>),
+	Binary(Box<Expression>, Token, Box<Expression>),
+	Logical(Box<Expression>, Token, Box<Expression>),
 	Grouping(Box<Expression>),
-    Variable(String),
-    Assign(String, Box<Expression>),
+    // resolved variable references also carry how many scopes out their
+    // binding lives, filled in by the resolver; None means global
+    Variable(Token, Option<usize>),
+    Assign(Token, Box<Expression>, Option<usize>),
+    // the closing paren locates errors raised by the call
+    Call(Box<Expression>, Token, Vec<Expression>),
+    Get(Box<Expression>, Token),
+    Set(Box<Expression>, Token, Box<Expression>),
+    This(Token, Option<usize>),
+    // `super` keyword, then method name
+    Super(Token, Token, Option<usize>),
 }
 
+const MAX_ARGUMENTS: usize = 255;
+
 
-pub struct Parser<'a> {
-	tokens: Vec<Token<'a>>,
+pub struct Parser {
+	tokens: Vec<Token>,
 	current: usize,
+    errors: Vec<LoxError>,
 }
 
-impl <'a> Parser <'a> {
-	pub fn new(tokens: Vec<Token<'a>>) -> Parser<'a> {
-		Parser {tokens: tokens, current: 0}
+impl Parser {
+	pub fn new(tokens: Vec<Token>) -> Parser {
+		Parser {tokens: tokens, current: 0, errors: vec![]}
 	}
 
-    pub fn parse(&mut self) -> Result<Vec<Statement>, &'static str> {
+    // keeps going after a syntax error so that every error in the
+    // source is reported in one pass
+    pub fn parse(&mut self) -> Result<Vec<Statement>, Vec<LoxError>> {
         let mut result = vec![];
         while !self.is_at_end() {
-            result.push(self.declaration()?);
+            if let Some(stmt) = self.declaration() {
+                result.push(stmt);
+            }
+        }
+        if !self.errors.is_empty() {
+            return Err(::std::mem::take(&mut self.errors));
         }
         Ok(result)
     }
 
-    fn declaration(&mut self) -> Result<Statement, &'static str> {
+    // a failed declaration is recorded and skipped, resuming at the
+    // next statement boundary
+    fn declaration(&mut self) -> Option<Statement> {
+        match self.try_declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(e) => {
+                self.errors.push(e);
+                self.synchronize();
+                None
+            },
+        }
+    }
+
+    fn try_declaration(&mut self) -> Result<Statement, LoxError> {
+        if self.match_types(vec![TokenType::Class]) {
+            return self.class_declaration();
+        }
+        if self.match_types(vec![TokenType::Fun]) {
+            return Ok(Statement::Function(Rc::new(self.function()?)));
+        }
         if self.match_types(vec![TokenType::Var]) {
             return self.var_declaration();
         }
         return self.statement();
     }
 
-    fn var_declaration(&mut self) -> Result<Statement, &'static str> {
-        let var_name;
-        {
-            let name_token = self.consume(TokenType::Identifier)?;
-            var_name = name_token.lexeme();
+    fn class_declaration(&mut self) -> Result<Statement, LoxError> {
+        let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+
+        let mut superclass = None;
+        if self.match_types(vec![TokenType::Less]) {
+            let super_name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+            if super_name.lexeme() == name.lexeme() {
+                self.errors.push(LoxError::syntax(&super_name, "A class can't inherit from itself."));
+            }
+            superclass = Some(Expression::Variable(super_name, None));
+        }
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
+
+        let mut methods = vec![];
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            methods.push(Rc::new(self.function()?));
         }
+        self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
+
+        Ok(Statement::Class(name, superclass, methods))
+    }
+
+    fn function(&mut self) -> Result<FunctionDecl, LoxError> {
+        let name = self.consume(TokenType::Identifier, "Expect function name.")?;
+
+        self.consume(TokenType::LeftParen, "Expect '(' after function name.")?;
+        let mut params = vec![];
+        if !self.check(&TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let error = LoxError::syntax(self.peek(), "Can't have more than 255 parameters.");
+                    self.errors.push(error);
+                }
+                params.push(self.consume(TokenType::Identifier, "Expect parameter name.")?);
+                if !self.match_types(vec![TokenType::Comma]) { break; }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+
+        self.consume(TokenType::LeftBrace, "Expect '{' before function body.")?;
+        let body = self.block()?;
+
+        Ok(FunctionDecl { name: name, params: params, body: body })
+    }
+
+    fn var_declaration(&mut self) -> Result<Statement, LoxError> {
+        let var_name = self.consume(TokenType::Identifier, "Expect variable name.")?;
 
         let mut initializer = Expression::Nil;
         if self.match_types(vec![TokenType::Equal]) {
             initializer = self.expression()?;
         }
 
-        self.consume(TokenType::Semicolon)?;
+        self.consume(TokenType::Semicolon, "Expect ';' after variable declaration.")?;
         Ok(Statement::VarDecl(var_name, initializer))
     }
 
-    fn statement(&mut self) -> Result<Statement, &'static str> {
+    fn statement(&mut self) -> Result<Statement, LoxError> {
+        if self.match_types(vec![TokenType::For]) {
+            return self.for_statement();
+        }
+        if self.match_types(vec![TokenType::If]) {
+            return self.if_statement();
+        }
         if self.match_types(vec![TokenType::Print]) {
             return self.print_statement();
         }
+        if self.match_types(vec![TokenType::Return]) {
+            return self.return_statement();
+        }
+        if self.match_types(vec![TokenType::While]) {
+            return self.while_statement();
+        }
+        if self.match_types(vec![TokenType::LeftBrace]) {
+            return Ok(Statement::Block(self.block()?));
+        }
         return self.expr_statement();
     }
 
-    fn print_statement(&mut self) -> Result<Statement, &'static str> {
+    fn block(&mut self) -> Result<Vec<Statement>, LoxError> {
+        let mut statements = vec![];
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        self.consume(TokenType::RightBrace, "Expect '}' after block.")?;
+        Ok(statements)
+    }
+
+    // for loops are desugared into an equivalent while loop,
+    // so the interpreter never sees them
+    fn for_statement(&mut self) -> Result<Statement, LoxError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+
+        let initializer;
+        if self.match_types(vec![TokenType::Semicolon]) {
+            initializer = None;
+        } else if self.match_types(vec![TokenType::Var]) {
+            initializer = Some(self.var_declaration()?);
+        } else {
+            initializer = Some(self.expr_statement()?);
+        }
+
+        let mut condition = Expression::True;
+        if !self.check(&TokenType::Semicolon) {
+            condition = self.expression()?;
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after loop condition.")?;
+
+        let mut increment = None;
+        if !self.check(&TokenType::RightParen) {
+            increment = Some(self.expression()?);
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+
+        if let Some(increment) = increment {
+            body = Statement::Block(vec![body, Statement::ExprStmt(increment)]);
+        }
+        body = Statement::While(condition, Box::new(body));
+        if let Some(initializer) = initializer {
+            body = Statement::Block(vec![initializer, body]);
+        }
+
+        return Ok(body);
+    }
+
+    fn if_statement(&mut self) -> Result<Statement, LoxError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = self.statement()?;
+        let mut else_branch = None;
+        if self.match_types(vec![TokenType::Else]) {
+            else_branch = Some(Box::new(self.statement()?));
+        }
+
+        return Ok(Statement::If(condition, Box::new(then_branch), else_branch));
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, LoxError> {
+        let keyword = self.previous().clone();
+        let mut value = Expression::Nil;
+        if !self.check(&TokenType::Semicolon) {
+            value = self.expression()?;
+        }
+        self.consume(TokenType::Semicolon, "Expect ';' after return value.")?;
+        return Ok(Statement::Return(keyword, value));
+    }
+
+    fn while_statement(&mut self) -> Result<Statement, LoxError> {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = self.statement()?;
+
+        return Ok(Statement::While(condition, Box::new(body)));
+    }
+
+    fn print_statement(&mut self) -> Result<Statement, LoxError> {
+        let keyword = self.previous().clone();
         let expr = self.expression()?;
-        self.consume(TokenType::Semicolon)?;
-        return Ok(Statement::PrintStmt(expr));
+        self.consume(TokenType::Semicolon, "Expect ';' after value.")?;
+        return Ok(Statement::PrintStmt(keyword, expr));
     }
 
-    fn expr_statement(&mut self) -> Result<Statement, &'static str> {
+    fn expr_statement(&mut self) -> Result<Statement, LoxError> {
         let expr = self.expression()?;
-        self.consume(TokenType::Semicolon)?;
+        self.consume(TokenType::Semicolon, "Expect ';' after expression.")?;
         return Ok(Statement::ExprStmt(expr));
     }
 
-	pub fn expression(&mut self) -> Result<Expression, &'static str> {
+	pub fn expression(&mut self) -> Result<Expression, LoxError> {
 		return self.assignment();
 	}
 
-	fn assignment(&mut self) -> Result<Expression, &'static str> {
-		let expr = self.equality()?;
+	fn assignment(&mut self) -> Result<Expression, LoxError> {
+		let expr = self.or()?;
 
 		if self.match_types(vec![TokenType::Equal]) {
+			let equals = self.previous().clone();
 			let value = self.assignment()?;
 
-			if let Expression::Variable(var_name) = expr {
-				return Ok(Expression::Assign {0: var_name, 1: Box::new(value)});
+			if let Expression::Variable(var_name, _) = expr {
+				return Ok(Expression::Assign {0: var_name, 1: Box::new(value), 2: None});
+			}
+			if let Expression::Get(object, prop_name) = expr {
+				return Ok(Expression::Set {0: object, 1: prop_name, 2: Box::new(value)});
 			}
 
-			return Err("invalid assignment target");
+			// the parser isn't confused, so report without unwinding
+			self.errors.push(LoxError::syntax(&equals, "Invalid assignment target."));
 		}
 
 		return Ok(expr);
 
 	}
 
-	fn equality(&mut self) -> Result<Expression, &'static str> {
+	fn or(&mut self) -> Result<Expression, LoxError> {
+		let mut expr = self.and()?;
+
+		while self.match_types(vec![TokenType::Or]) {
+			let operator = self.previous().clone();
+			let right = self.and()?;
+			expr = Expression::Logical {0: Box::new(expr), 1: operator, 2: Box::new(right)};
+		}
+
+		return Ok(expr);
+	}
+
+	fn and(&mut self) -> Result<Expression, LoxError> {
+		let mut expr = self.equality()?;
+
+		while self.match_types(vec![TokenType::And]) {
+			let operator = self.previous().clone();
+			let right = self.equality()?;
+			expr = Expression::Logical {0: Box::new(expr), 1: operator, 2: Box::new(right)};
+		}
+
+		return Ok(expr);
+	}
+
+	fn equality(&mut self) -> Result<Expression, LoxError> {
 		let mut expr = self.comparison()?;
 
 		while self.match_types(vec![TokenType::BangEqual, TokenType::EqualEqual]) {
-			let operator = self.previous().token_type();
+			let operator = self.previous().clone();
 			let right = self.comparison()?;
 			expr = Expression::Binary {0: Box::new(expr), 1: operator, 2: Box::new(right)};
 		}
@@ -161,11 +419,11 @@ impl <'a> Parser <'a> {
 		return Ok(expr);
 	}
 
-	fn comparison(&mut self) -> Result<Expression, &'static str> {
+	fn comparison(&mut self) -> Result<Expression, LoxError> {
 		let mut expr = self.addition()?;
 
 		while self.match_types(vec![TokenType::Greater, TokenType::GreaterEqual, TokenType::Less, TokenType::LessEqual]) {
-			let operator = self.previous().token_type();
+			let operator = self.previous().clone();
 			let right = self.addition()?;
 			expr = Expression::Binary {0: Box::new(expr), 1: operator, 2: Box::new(right)};
 		}
@@ -173,11 +431,11 @@ impl <'a> Parser <'a> {
 		return Ok(expr);
 	}
 
-	fn addition(&mut self) -> Result<Expression, &'static str> {
+	fn addition(&mut self) -> Result<Expression, LoxError> {
 		let mut expr = self.multiplication()?;
 
 		while self.match_types(vec![TokenType::Minus, TokenType::Plus]) {
-			let operator = self.previous().token_type();
+			let operator = self.previous().clone();
 			let right = self.multiplication()?;
 			expr = Expression::Binary {0: Box::new(expr), 1: operator, 2: Box::new(right)};
 		}
@@ -185,11 +443,11 @@ impl <'a> Parser <'a> {
 		return Ok(expr);
 	}
 
-	fn multiplication(&mut self) -> Result<Expression, &'static str> {
+	fn multiplication(&mut self) -> Result<Expression, LoxError> {
 		let mut expr = self.unary()?;
 
 		while self.match_types(vec![TokenType::Slash, TokenType::Star]) {
-			let operator = self.previous().token_type();
+			let operator = self.previous().clone();
 			let right = self.unary()?;
 			expr = Expression::Binary {0: Box::new(expr), 1: operator, 2: Box::new(right)};
 		}
@@ -197,19 +455,61 @@ impl <'a> Parser <'a> {
 		return Ok(expr);
 	}
 
-	fn unary(&mut self) -> Result<Expression, &'static str> {
+	fn unary(&mut self) -> Result<Expression, LoxError> {
 		if self.match_types(vec![TokenType::Bang, TokenType::Minus]) {
-			let operator = self.previous().token_type();
+			let operator = self.previous().clone();
 			let right = self.unary()?;
 			return Ok(Expression::Unary {0: operator, 1: Box::new(right)});
 		}
-		return self.primary();
+		return self.call();
+	}
+
+	fn call(&mut self) -> Result<Expression, LoxError> {
+		let mut expr = self.primary()?;
+
+		loop {
+			if self.match_types(vec![TokenType::LeftParen]) {
+				expr = self.finish_call(expr)?;
+			} else if self.match_types(vec![TokenType::Dot]) {
+				let prop_name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
+				expr = Expression::Get {0: Box::new(expr), 1: prop_name};
+			} else {
+				break;
+			}
+		}
+
+		return Ok(expr);
+	}
+
+	fn finish_call(&mut self, callee: Expression) -> Result<Expression, LoxError> {
+		let mut arguments = vec![];
+		if !self.check(&TokenType::RightParen) {
+			loop {
+				if arguments.len() >= MAX_ARGUMENTS {
+					let error = LoxError::syntax(self.peek(), "Can't have more than 255 arguments.");
+					self.errors.push(error);
+				}
+				arguments.push(self.expression()?);
+				if !self.match_types(vec![TokenType::Comma]) { break; }
+			}
+		}
+		let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+
+		return Ok(Expression::Call {0: Box::new(callee), 1: paren, 2: arguments});
 	}
 
-	fn primary(&mut self) -> Result<Expression, &'static str> {
+	fn primary(&mut self) -> Result<Expression, LoxError> {
 		if self.match_types(vec![TokenType::False]) { return Ok(Expression::False); }
 		if self.match_types(vec![TokenType::True])  { return Ok(Expression::True);  }
 		if self.match_types(vec![TokenType::Nil])   { return Ok(Expression::Nil);   }
+		if self.match_types(vec![TokenType::This])  { return Ok(Expression::This(self.previous().clone(), None)); }
+
+		if self.match_types(vec![TokenType::Super]) {
+			let keyword = self.previous().clone();
+			self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+			let method_name = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+			return Ok(Expression::Super {0: keyword, 1: method_name, 2: None});
+		}
 
 		if self.match_types(vec![TokenType::StringLiteral]) {
 			return Ok(Expression::Literal {0: self.previous().literal() });
@@ -220,15 +520,32 @@ impl <'a> Parser <'a> {
 
 		if self.match_types(vec![TokenType::LeftParen]) {
 			let expr = self.expression()?;
-			self.consume(TokenType::RightParen)?;
+			self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
 			return Ok(Expression::Grouping {0: Box::new(expr)});
 		}
 
         if self.match_types(vec![TokenType::Identifier]) {
-            return Ok(Expression::Variable {0: self.previous().lexeme() });
+            return Ok(Expression::Variable {0: self.previous().clone(), 1: None});
         }
 
-		Err("could not match primary")
+		Err(LoxError::syntax(self.peek(), "Expect expression."))
+	}
+
+	// discards tokens until the likely start of the next statement
+	fn synchronize(&mut self) {
+		self.advance();
+
+		while !self.is_at_end() {
+			if self.previous().token_type() == TokenType::Semicolon { return; }
+
+			match self.peek().token_type() {
+				TokenType::Class | TokenType::Fun | TokenType::Var | TokenType::For |
+				TokenType::If | TokenType::While | TokenType::Print | TokenType::Return => { return; },
+				_ => {},
+			}
+
+			self.advance();
+		}
 	}
 
 	fn match_types(&mut self, types: Vec<TokenType>) -> bool {
@@ -250,21 +567,21 @@ impl <'a> Parser <'a> {
 		self.peek().token_type() == TokenType::Eof
 	}
 
-	fn consume(&mut self, token_type: TokenType) -> Result<&Token<'a>, &'static str> {
-		if self.check(&token_type) { return Ok(self.advance()); }
-		Err("desired token not found")
+	fn consume(&mut self, token_type: TokenType, message: &str) -> Result<Token, LoxError> {
+		if self.check(&token_type) { return Ok(self.advance().clone()); }
+		Err(LoxError::syntax(self.peek(), message))
 	}
 
-	fn advance(&mut self) -> &Token<'a> {
+	fn advance(&mut self) -> &Token {
 		if !self.is_at_end() {self.current += 1; }
 		self.previous()
 	}
 
-	fn previous(&self) -> &Token<'a> {
+	fn previous(&self) -> &Token {
 		&(self.tokens[self.current - 1])
 	}
 
-	fn peek(&self) -> &Token<'a> {
+	fn peek(&self) -> &Token {
 		&(self.tokens[self.current])
 	}
 }
\ No newline at end of file
@@ -0,0 +1,494 @@
+use parse::{Expression, FunctionDecl, Statement};
+use scanner::TokenType;
+
+// Renders trees back to text, either as canonical Lox source that parses
+// to the same tree, or in the fully parenthesized prefix form the book
+// uses to show a tree's structure.
+
+// how tightly each form of expression binds, loosest first, following
+// the grammar in parse.rs
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+	Assignment,
+	Or,
+	And,
+	Equality,
+	Comparison,
+	Term,
+	Factor,
+	Unary,
+	Call,
+	Primary,
+}
+
+impl Precedence {
+	// the level a binary operator's right operand must bind at, since all
+	// of them associate to the left
+	fn next(self) -> Precedence {
+		match self {
+			Precedence::Assignment => Precedence::Or,
+			Precedence::Or => Precedence::And,
+			Precedence::And => Precedence::Equality,
+			Precedence::Equality => Precedence::Comparison,
+			Precedence::Comparison => Precedence::Term,
+			Precedence::Term => Precedence::Factor,
+			Precedence::Factor => Precedence::Unary,
+			Precedence::Unary => Precedence::Call,
+			Precedence::Call | Precedence::Primary => Precedence::Primary,
+		}
+	}
+}
+
+/// Renders a program as Lox source, one declaration per line, with blocks
+/// indented by two spaces. Parentheses appear only where precedence needs
+/// them, whatever groupings the tree had.
+///
+/// ```
+/// use rustlox::{printer, Interner, Parser, Scanner};
+///
+/// let tokens = Scanner::new("if ((a)) print (1 + 2) * 3; else { var b = -(c); }", &Interner::new()).scan_tokens().unwrap();
+/// let program = Parser::new(tokens).parse().unwrap();
+/// assert_eq!(printer::print_program(&program), "if (a) print (1 + 2) * 3; else {\n  var b = -c;\n}\n");
+/// ```
+pub fn print_program(program: &[Statement]) -> String {
+	let mut printer = SourcePrinter { out: String::new(), indent: 0 };
+	for stmt in program {
+		printer.statement(stmt);
+	}
+	printer.out
+}
+
+pub fn print_expression(expr: &Expression) -> String {
+	let mut printer = SourcePrinter { out: String::new(), indent: 0 };
+	printer.expression(expr);
+	printer.out
+}
+
+/// Renders a program in prefix form, one top-level declaration per line.
+/// Unlike Lox source, this shows every grouping the parser kept.
+///
+/// ```
+/// use rustlox::{printer, Interner, Parser, Scanner};
+///
+/// let tokens = Scanner::new("-123 * (45.67);", &Interner::new()).scan_tokens().unwrap();
+/// let program = Parser::new(tokens).parse().unwrap();
+/// assert_eq!(printer::lisp_program(&program), "(; (* (- 123) (group 45.67)))\n");
+/// ```
+pub fn lisp_program(program: &[Statement]) -> String {
+	let mut out = String::new();
+	for stmt in program {
+		lisp_statement(&mut out, stmt);
+		out.push('\n');
+	}
+	out
+}
+
+pub fn lisp_expression(expr: &Expression) -> String {
+	let mut out = String::new();
+	lisp_expr(&mut out, expr);
+	out
+}
+
+struct SourcePrinter {
+	out: String,
+	indent: usize,
+}
+
+impl SourcePrinter {
+	fn statement(&mut self, stmt: &Statement) {
+		self.write_indent();
+		self.inline_statement(stmt);
+		self.out.push('\n');
+	}
+
+	// writes a statement from the current position, leaving the line open
+	fn inline_statement(&mut self, stmt: &Statement) {
+		match *stmt {
+			Statement::ExprStmt(ref e) => {
+				self.expression(e);
+				self.out.push(';');
+			},
+			Statement::PrintStmt(_, ref e) => {
+				self.out.push_str("print ");
+				self.expression(e);
+				self.out.push(';');
+			},
+			Statement::VarDecl(ref var_name, ref initializer) => {
+				self.out.push_str("var ");
+				self.out.push_str(var_name.symbol());
+				// the parser leaves a missing initializer as nil
+				if *initializer != Expression::Nil {
+					self.out.push_str(" = ");
+					self.expression(initializer);
+				}
+				self.out.push(';');
+			},
+			Statement::Block(ref statements) => self.block(statements),
+			Statement::If(ref condition, ref then_branch, ref else_branch) => {
+				self.out.push_str("if (");
+				self.expression(condition);
+				self.out.push_str(") ");
+				self.inline_statement(then_branch);
+				if let Some(ref else_branch) = *else_branch {
+					self.out.push_str(" else ");
+					self.inline_statement(else_branch);
+				}
+			},
+			Statement::While(ref condition, ref body) => {
+				self.out.push_str("while (");
+				self.expression(condition);
+				self.out.push_str(") ");
+				self.inline_statement(body);
+			},
+			Statement::Function(ref declaration) => {
+				self.out.push_str("fun ");
+				self.function(declaration);
+			},
+			Statement::Return(_, ref e) => {
+				self.out.push_str("return");
+				if *e != Expression::Nil {
+					self.out.push(' ');
+					self.expression(e);
+				}
+				self.out.push(';');
+			},
+			Statement::Class(ref class_name, ref superclass, ref methods) => {
+				self.out.push_str("class ");
+				self.out.push_str(class_name.symbol());
+				if let Some(ref superclass) = *superclass {
+					self.out.push_str(" < ");
+					self.expression(superclass);
+				}
+				if methods.is_empty() {
+					self.out.push_str(" {}");
+					return;
+				}
+				self.out.push_str(" {\n");
+				self.indent += 1;
+				for method in methods {
+					self.write_indent();
+					self.function(method);
+					self.out.push('\n');
+				}
+				self.indent -= 1;
+				self.write_indent();
+				self.out.push('}');
+			},
+		}
+	}
+
+	fn block(&mut self, statements: &[Statement]) {
+		if statements.is_empty() {
+			self.out.push_str("{}");
+			return;
+		}
+		self.out.push_str("{\n");
+		self.indent += 1;
+		for stmt in statements {
+			self.statement(stmt);
+		}
+		self.indent -= 1;
+		self.write_indent();
+		self.out.push('}');
+	}
+
+	fn function(&mut self, declaration: &FunctionDecl) {
+		self.out.push_str(declaration.name.symbol());
+		self.out.push('(');
+		for (i, param) in declaration.params.iter().enumerate() {
+			if i > 0 {
+				self.out.push_str(", ");
+			}
+			self.out.push_str(param.symbol());
+		}
+		self.out.push_str(") ");
+		self.block(&declaration.body);
+	}
+
+	fn write_indent(&mut self) {
+		for _ in 0..self.indent {
+			self.out.push_str("  ");
+		}
+	}
+
+	fn expression(&mut self, expr: &Expression) {
+		self.operand(expr, Precedence::Assignment);
+	}
+
+	// writes an expression where the grammar expects one binding at least
+	// as tightly as `min`, parenthesizing it if it doesn't
+	fn operand(&mut self, expr: &Expression, min: Precedence) {
+		let expr = ungrouped(expr);
+		if precedence(expr) < min {
+			self.out.push('(');
+			self.operand(expr, Precedence::Assignment);
+			self.out.push(')');
+			return;
+		}
+		match *expr {
+			Expression::Number(n) => self.out.push_str(&source_number(n)),
+			Expression::Literal(ref s) => self.out.push_str(&quoted(s)),
+			Expression::True => self.out.push_str("true"),
+			Expression::False => self.out.push_str("false"),
+			Expression::Nil => self.out.push_str("nil"),
+			Expression::Unary(ref operator, ref operand) => {
+				self.out.push_str(operator.symbol());
+				let start = self.out.len();
+				self.operand(operand, Precedence::Unary);
+				// `- -x` rather than `--x`, which reads as a decrement
+				if operator.token_type() == TokenType::Minus && self.out[start..].starts_with('-') {
+					self.out.insert(start, ' ');
+				}
+			},
+			Expression::Binary(ref left, ref operator, ref right) |
+			Expression::Logical(ref left, ref operator, ref right) => {
+				let level = precedence(expr);
+				self.operand(left, level);
+				self.out.push(' ');
+				self.out.push_str(operator.symbol());
+				self.out.push(' ');
+				self.operand(right, level.next());
+			},
+			Expression::Grouping(_) => unreachable!("groupings are unwrapped above"),
+			Expression::Variable(ref var_name, _) => self.out.push_str(var_name.symbol()),
+			Expression::Assign(ref var_name, ref value, _) => {
+				self.out.push_str(var_name.symbol());
+				self.out.push_str(" = ");
+				self.operand(value, Precedence::Assignment);
+			},
+			Expression::Call(ref callee, _, ref arguments) => {
+				self.operand(callee, Precedence::Call);
+				self.out.push('(');
+				for (i, argument) in arguments.iter().enumerate() {
+					if i > 0 {
+						self.out.push_str(", ");
+					}
+					self.operand(argument, Precedence::Assignment);
+				}
+				self.out.push(')');
+			},
+			Expression::Get(ref object, ref prop_name) => {
+				self.operand(object, Precedence::Call);
+				self.out.push('.');
+				self.out.push_str(prop_name.symbol());
+			},
+			Expression::Set(ref object, ref prop_name, ref value) => {
+				self.operand(object, Precedence::Call);
+				self.out.push('.');
+				self.out.push_str(prop_name.symbol());
+				self.out.push_str(" = ");
+				self.operand(value, Precedence::Assignment);
+			},
+			Expression::This(..) => self.out.push_str("this"),
+			Expression::Super(_, ref method_name, _) => {
+				self.out.push_str("super.");
+				self.out.push_str(method_name.symbol());
+			},
+		}
+	}
+}
+
+fn ungrouped(mut expr: &Expression) -> &Expression {
+	while let Expression::Grouping(ref inner) = *expr {
+		expr = inner;
+	}
+	expr
+}
+
+fn precedence(expr: &Expression) -> Precedence {
+	match *ungrouped(expr) {
+		Expression::Assign(..) | Expression::Set(..) => Precedence::Assignment,
+		Expression::Logical(_, ref operator, _) => match operator.token_type() {
+			TokenType::Or => Precedence::Or,
+			_ => Precedence::And,
+		},
+		Expression::Binary(_, ref operator, _) => match operator.token_type() {
+			TokenType::EqualEqual | TokenType::BangEqual => Precedence::Equality,
+			TokenType::Plus | TokenType::Minus => Precedence::Term,
+			TokenType::Star | TokenType::Slash => Precedence::Factor,
+			_ => Precedence::Comparison,
+		},
+		Expression::Unary(..) => Precedence::Unary,
+		// folded constants can be negative, which Lox writes as a negation
+		Expression::Number(n) if n.is_finite() && n.is_sign_negative() => Precedence::Unary,
+		Expression::Call(..) | Expression::Get(..) => Precedence::Call,
+		_ => Precedence::Primary,
+	}
+}
+
+// Lox has no literals for infinities or NaN, which folding can produce,
+// so those are written as the divisions that make them
+fn source_number(n: f64) -> String {
+	if n.is_nan() {
+		return "(0 / 0)".to_string();
+	}
+	if n.is_infinite() {
+		return if n > 0.0 { "(1 / 0)".to_string() } else { "(-1 / 0)".to_string() };
+	}
+	n.to_string()
+}
+
+// a string literal with the escapes the scanner decodes put back
+fn quoted(s: &str) -> String {
+	let mut literal = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'\n' => literal.push_str("\\n"),
+			'\t' => literal.push_str("\\t"),
+			'\r' => literal.push_str("\\r"),
+			'\0' => literal.push_str("\\0"),
+			'"' => literal.push_str("\\\""),
+			'\\' => literal.push_str("\\\\"),
+			c if c.is_control() => literal.push_str(&format!("\\u{{{:x}}}", c as u32)),
+			c => literal.push(c),
+		}
+	}
+	literal.push('"');
+	literal
+}
+
+fn lisp_statement(out: &mut String, stmt: &Statement) {
+	match *stmt {
+		Statement::ExprStmt(ref e) => lisp_list(out, ";", &[e]),
+		Statement::PrintStmt(_, ref e) => lisp_list(out, "print", &[e]),
+		Statement::VarDecl(ref var_name, ref initializer) => {
+			out.push_str("(var ");
+			out.push_str(var_name.symbol());
+			if *initializer != Expression::Nil {
+				out.push(' ');
+				lisp_expr(out, initializer);
+			}
+			out.push(')');
+		},
+		Statement::Block(ref statements) => {
+			out.push_str("(block");
+			for stmt in statements {
+				out.push(' ');
+				lisp_statement(out, stmt);
+			}
+			out.push(')');
+		},
+		Statement::If(ref condition, ref then_branch, ref else_branch) => {
+			out.push_str("(if ");
+			lisp_expr(out, condition);
+			out.push(' ');
+			lisp_statement(out, then_branch);
+			if let Some(ref else_branch) = *else_branch {
+				out.push(' ');
+				lisp_statement(out, else_branch);
+			}
+			out.push(')');
+		},
+		Statement::While(ref condition, ref body) => {
+			out.push_str("(while ");
+			lisp_expr(out, condition);
+			out.push(' ');
+			lisp_statement(out, body);
+			out.push(')');
+		},
+		Statement::Function(ref declaration) => lisp_function(out, "fun", declaration),
+		Statement::Return(_, ref e) => {
+			if *e == Expression::Nil {
+				out.push_str("(return)");
+			} else {
+				lisp_list(out, "return", &[e]);
+			}
+		},
+		Statement::Class(ref class_name, ref superclass, ref methods) => {
+			out.push_str("(class ");
+			out.push_str(class_name.symbol());
+			if let Some(ref superclass) = *superclass {
+				out.push_str(" < ");
+				lisp_expr(out, superclass);
+			}
+			for method in methods {
+				out.push(' ');
+				lisp_function(out, "method", method);
+			}
+			out.push(')');
+		},
+	}
+}
+
+fn lisp_function(out: &mut String, keyword: &str, declaration: &FunctionDecl) {
+	out.push('(');
+	out.push_str(keyword);
+	out.push(' ');
+	out.push_str(declaration.name.symbol());
+	out.push_str(" (");
+	for (i, param) in declaration.params.iter().enumerate() {
+		if i > 0 {
+			out.push(' ');
+		}
+		out.push_str(param.symbol());
+	}
+	out.push(')');
+	for stmt in &declaration.body {
+		out.push(' ');
+		lisp_statement(out, stmt);
+	}
+	out.push(')');
+}
+
+fn lisp_expr(out: &mut String, expr: &Expression) {
+	match *expr {
+		Expression::Number(n) => out.push_str(&n.to_string()),
+		Expression::Literal(ref s) => out.push_str(&quoted(s)),
+		Expression::True => out.push_str("true"),
+		Expression::False => out.push_str("false"),
+		Expression::Nil => out.push_str("nil"),
+		Expression::Unary(ref operator, ref operand) => lisp_list(out, operator.symbol(), &[&**operand]),
+		Expression::Binary(ref left, ref operator, ref right) |
+		Expression::Logical(ref left, ref operator, ref right) => lisp_list(out, operator.symbol(), &[&**left, &**right]),
+		Expression::Grouping(ref inner) => lisp_list(out, "group", &[&**inner]),
+		Expression::Variable(ref var_name, _) => out.push_str(var_name.symbol()),
+		Expression::Assign(ref var_name, ref value, _) => {
+			out.push_str("(= ");
+			out.push_str(var_name.symbol());
+			out.push(' ');
+			lisp_expr(out, value);
+			out.push(')');
+		},
+		Expression::Call(ref callee, _, ref arguments) => {
+			out.push_str("(call ");
+			lisp_expr(out, callee);
+			for argument in arguments {
+				out.push(' ');
+				lisp_expr(out, argument);
+			}
+			out.push(')');
+		},
+		Expression::Get(ref object, ref prop_name) => {
+			out.push_str("(. ");
+			lisp_expr(out, object);
+			out.push(' ');
+			out.push_str(prop_name.symbol());
+			out.push(')');
+		},
+		Expression::Set(ref object, ref prop_name, ref value) => {
+			out.push_str("(= (. ");
+			lisp_expr(out, object);
+			out.push(' ');
+			out.push_str(prop_name.symbol());
+			out.push_str(") ");
+			lisp_expr(out, value);
+			out.push(')');
+		},
+		Expression::This(..) => out.push_str("this"),
+		Expression::Super(_, ref method_name, _) => {
+			out.push_str("(super ");
+			out.push_str(method_name.symbol());
+			out.push(')');
+		},
+	}
+}
+
+fn lisp_list(out: &mut String, head: &str, exprs: &[&Expression]) {
+	out.push('(');
+	out.push_str(head);
+	for expr in exprs {
+		out.push(' ');
+		lisp_expr(out, expr);
+	}
+	out.push(')');
+}
@@ -0,0 +1,241 @@
+use error::LoxError;
+use parse::{declaration_mut, Expression, FunctionDecl, Statement};
+use scanner::Token;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+	None,
+	Function,
+	Initializer,
+	Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+	None,
+	Class,
+	Subclass,
+}
+
+// Walks the tree once before it runs, recording on each variable reference
+// how many scopes out its binding lives. References left unresolved are globals.
+pub struct Resolver {
+	// name -> whether its initializer has finished
+	scopes: Vec<HashMap<String, bool>>,
+	current_function: FunctionType,
+	current_class: ClassType,
+	// static errors don't stop the walk, so all of them are reported together
+	errors: Vec<LoxError>,
+}
+
+impl Resolver {
+	pub fn new() -> Self {
+		Resolver { scopes: vec![], current_function: FunctionType::None, current_class: ClassType::None, errors: vec![] }
+	}
+
+	pub fn resolve(&mut self, program: &mut [Statement]) -> Result<(), Vec<LoxError>> {
+		self.resolve_statements(program);
+		if !self.errors.is_empty() {
+			return Err(::std::mem::take(&mut self.errors));
+		}
+		Ok(())
+	}
+
+	fn resolve_statements(&mut self, statements: &mut [Statement]) {
+		for stmt in statements.iter_mut() {
+			self.resolve_statement(stmt);
+		}
+	}
+
+	fn resolve_statement(&mut self, stmt: &mut Statement) {
+		match *stmt {
+			Statement::ExprStmt(ref mut e) | Statement::PrintStmt(_, ref mut e) => {
+				self.resolve_expression(e);
+			},
+			Statement::VarDecl(ref var_name, ref mut initializer) => {
+				self.declare(var_name);
+				self.resolve_expression(initializer);
+				self.define(&var_name.lexeme());
+			},
+			Statement::Block(ref mut statements) => {
+				self.begin_scope();
+				self.resolve_statements(statements);
+				self.end_scope();
+			},
+			Statement::If(ref mut condition, ref mut then_branch, ref mut else_branch) => {
+				self.resolve_expression(condition);
+				self.resolve_statement(then_branch);
+				if let Some(ref mut else_branch) = *else_branch {
+					self.resolve_statement(else_branch);
+				}
+			},
+			Statement::While(ref mut condition, ref mut body) => {
+				self.resolve_expression(condition);
+				self.resolve_statement(body);
+			},
+			Statement::Function(ref mut declaration) => {
+				// defined before the body so the function can refer to itself
+				self.declare(&declaration.name);
+				self.define(&declaration.name.lexeme());
+				self.resolve_function(declaration_mut(declaration), FunctionType::Function);
+			},
+			Statement::Return(ref keyword, ref mut e) => {
+				if self.current_function == FunctionType::None {
+					self.errors.push(LoxError::resolution(keyword, "Can't return from top-level code."));
+				}
+				if *e != Expression::Nil {
+					if self.current_function == FunctionType::Initializer {
+						self.errors.push(LoxError::resolution(keyword, "Can't return a value from an initializer."));
+					}
+					self.resolve_expression(e);
+				}
+			},
+			Statement::Class(ref class_name, ref mut superclass, ref mut methods) => {
+				let enclosing_class = self.current_class;
+				self.current_class = ClassType::Class;
+				self.resolve_class(class_name, superclass, methods);
+				self.current_class = enclosing_class;
+			},
+		}
+	}
+
+	fn resolve_class(&mut self, class_name: &Token, superclass: &mut Option<Expression>, methods: &mut [Rc<FunctionDecl>]) {
+		self.declare(class_name);
+		self.define(&class_name.lexeme());
+
+		if let Some(ref mut superclass) = *superclass {
+			self.current_class = ClassType::Subclass;
+			self.resolve_expression(superclass);
+			self.begin_scope();
+			self.define("super");
+		}
+
+		self.begin_scope();
+		self.define("this");
+
+		for method in methods.iter_mut() {
+			let mut function_type = FunctionType::Method;
+			if method.name.lexeme() == "init" {
+				function_type = FunctionType::Initializer;
+			}
+			self.resolve_function(declaration_mut(method), function_type);
+		}
+
+		self.end_scope();
+		if superclass.is_some() {
+			self.end_scope();
+		}
+	}
+
+	fn resolve_function(&mut self, declaration: &mut FunctionDecl, function_type: FunctionType) {
+		let enclosing_function = self.current_function;
+		self.current_function = function_type;
+		self.begin_scope();
+
+		for param in &declaration.params {
+			self.declare(param);
+			self.define(&param.lexeme());
+		}
+		self.resolve_statements(&mut declaration.body);
+
+		self.end_scope();
+		self.current_function = enclosing_function;
+	}
+
+	fn resolve_expression(&mut self, expr: &mut Expression) {
+		match *expr {
+			Expression::Number(_) | Expression::Literal(_) |
+			Expression::True | Expression::False | Expression::Nil => {},
+			Expression::Unary(_, ref mut be) | Expression::Grouping(ref mut be) => {
+				self.resolve_expression(be);
+			},
+			Expression::Binary(ref mut bel, _, ref mut ber) | Expression::Logical(ref mut bel, _, ref mut ber) => {
+				self.resolve_expression(bel);
+				self.resolve_expression(ber);
+			},
+			Expression::Variable(ref var_name, ref mut depth) => {
+				if let Some(scope) = self.scopes.last() {
+					if scope.get(&var_name.lexeme()) == Some(&false) {
+						self.errors.push(LoxError::resolution(var_name, "Can't read local variable in its own initializer."));
+					}
+				}
+				*depth = self.resolve_local(&var_name.lexeme());
+			},
+			Expression::Assign(ref var_name, ref mut be, ref mut depth) => {
+				self.resolve_expression(be);
+				*depth = self.resolve_local(&var_name.lexeme());
+			},
+			Expression::Call(ref mut callee, _, ref mut arguments) => {
+				self.resolve_expression(callee);
+				for argument in arguments.iter_mut() {
+					self.resolve_expression(argument);
+				}
+			},
+			Expression::Get(ref mut object, _) => {
+				self.resolve_expression(object);
+			},
+			Expression::Set(ref mut object, _, ref mut be) => {
+				self.resolve_expression(be);
+				self.resolve_expression(object);
+			},
+			Expression::This(ref keyword, ref mut depth) => {
+				if self.current_class == ClassType::None {
+					self.errors.push(LoxError::resolution(keyword, "Can't use 'this' outside of a class."));
+				}
+				*depth = self.resolve_local("this");
+			},
+			Expression::Super(ref keyword, _, ref mut depth) => {
+				match self.current_class {
+					ClassType::None => {
+						self.errors.push(LoxError::resolution(keyword, "Can't use 'super' outside of a class."));
+					},
+					ClassType::Class => {
+						self.errors.push(LoxError::resolution(keyword, "Can't use 'super' in a class with no superclass."));
+					},
+					ClassType::Subclass => {},
+				}
+				*depth = self.resolve_local("super");
+			},
+		}
+	}
+
+	fn resolve_local(&self, var_name: &str) -> Option<usize> {
+		for (depth, scope) in self.scopes.iter().rev().enumerate() {
+			if scope.contains_key(var_name) {
+				return Some(depth);
+			}
+		}
+		None
+	}
+
+	fn begin_scope(&mut self) {
+		self.scopes.push(HashMap::new());
+	}
+
+	fn end_scope(&mut self) {
+		self.scopes.pop();
+	}
+
+	fn declare(&mut self, var_name: &Token) {
+		if let Some(scope) = self.scopes.last_mut() {
+			if scope.contains_key(&var_name.lexeme()) {
+				self.errors.push(LoxError::resolution(var_name, "Already a variable with this name in this scope."));
+			}
+			scope.insert(var_name.lexeme(), false);
+		}
+	}
+
+	fn define(&mut self, var_name: &str) {
+		if let Some(scope) = self.scopes.last_mut() {
+			scope.insert(var_name.to_string(), true);
+		}
+	}
+}
+
+impl Default for Resolver {
+	fn default() -> Self {
+		Resolver::new()
+	}
+}
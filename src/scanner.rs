@@ -1,4 +1,7 @@
+use error::LoxError;
+use intern::{Interner, Symbol};
 use std::fmt;
+use unicode_xid::UnicodeXID;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
@@ -20,7 +23,6 @@ pub enum TokenType {
 	Print, Return, Super, This, True, Var, While,
 
 	Eof,
-    Unknown,
 }
 
 fn keyword_to_token(word: &str) -> TokenType {
@@ -45,95 +47,130 @@ fn keyword_to_token(word: &str) -> TokenType {
     }
 }
 
-#[derive(Debug)]
-pub struct Token<'a> {
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
     token_type: TokenType,
-    lexeme: &'a str,
-    literal: &'a str,
+    lexeme: Symbol,
+    literal: Symbol,
     line: usize,
+    column: usize,
 }
 
-impl<'a> Token<'a> {
-	pub fn new(token_type: TokenType, lexeme: &'a str, literal: &'a str, line: usize) -> Token<'a> {
-		Token{ token_type: token_type, lexeme: lexeme, literal: literal, line: line}
+impl Token {
+	// the token's text isn't interned; the scanner makes its own tokens
+	pub fn new(token_type: TokenType, lexeme: &str, literal: &str, line: usize, column: usize) -> Token {
+		Token{ token_type: token_type, lexeme: Symbol::from(lexeme), literal: Symbol::from(literal), line: line, column: column}
 	}
 
     pub fn token_type(&self) -> TokenType {
         return self.token_type.clone();
     }
 
-    pub fn literal(&self) -> String {
-        return self.literal.to_string();
+    pub fn literal(&self) -> Symbol {
+        return self.literal.clone();
     }
 
     pub fn lexeme(&self) -> String {
         return self.lexeme.to_string();
     }
 
-    // pub fn line(&self) -> usize {
-    //     return self.line;
-    // }
+    // the lexeme as interned by the scanner, for cheap name lookups
+    pub fn symbol(&self) -> &Symbol {
+        return &self.lexeme;
+    }
+
+    pub fn line(&self) -> usize {
+        return self.line;
+    }
+
+    pub fn column(&self) -> usize {
+        return self.column;
+    }
 }
 
-impl<'a> fmt::Display for Token<'a> {
+impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "({:?}, {}, ln {})", self.token_type, self.lexeme, self.line)
+        write!(f, "({:?}, {}, ln {}:{})", self.token_type, self.lexeme, self.line, self.column)
     }
 }
 
-pub struct Scanner<'a> {
-	source_text: &'a str,
-	tokens: Vec<Token<'a>>,
+// Scans by character rather than by byte, so multibyte UTF-8 text in
+// strings, comments and identifiers is never split; columns count characters.
+pub struct Scanner {
+	source_text: Vec<char>,
+	tokens: Vec<Token>,
+    errors: Vec<LoxError>,
 	ix: usize,
 	start: usize,
 	line: usize,
+    // index where the current line begins, for column numbers
+    line_start: usize,
+    // position of the first character of the token being scanned
+    start_line: usize,
+    start_column: usize,
+    interner: Interner,
 }
 
-impl<'a> Scanner<'a> {
-	pub fn new(source_text: &'a str) -> Scanner<'a> {
-		Scanner { source_text: source_text, tokens: vec![] , ix: 0, start: 0, line: 1}
+impl Scanner {
+    // token text is interned in the given table; an interpreter only
+    // recognises names interned in its own
+	pub fn new(source_text: &str, interner: &Interner) -> Scanner {
+		Scanner { source_text: source_text.chars().collect(), tokens: vec![], errors: vec![], ix: 0, start: 0, line: 1,
+                  line_start: 0, start_line: 1, start_column: 1, interner: interner.clone()}
 	}
 
-	pub fn scan_tokens(mut self) -> Vec<Token<'a>> {
+    // lexical errors don't stop the scan, so all of them are reported together
+	pub fn scan_tokens(mut self) -> Result<Vec<Token>, Vec<LoxError>> {
 		while self.ix < self.source_text.len() {
             // ix is at start of next token
-			self.start = self.ix;
+            self.mark_start();
 			self.scan_token();
 		}
+        self.mark_start();
         self.add_token(TokenType::Eof);
-		return self.tokens;
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+		return Ok(self.tokens);
 	}
 
+    fn mark_start(&mut self) {
+        self.start = self.ix;
+        self.start_line = self.line;
+        self.start_column = self.ix - self.line_start + 1;
+    }
+
 	fn scan_token(&mut self) {
-	    let c = self.source_text.as_bytes()[self.ix];
+	    let c = self.source_text[self.ix];
 
         // handle comments and slash
-        if c == b'/' {
-            if self.match_next(b'/') {
-                while self.this_char() != b'\n' && self.ix + 1 < self.source_text.len() {
+        if c == '/' {
+            if self.match_next('/') {
+                while self.ix < self.source_text.len() && self.this_char() != '\n' {
                     self.ix += 1;
                 }
                 return;
             } else {
-                self.add_token(TokenType::Slash);
                 self.ix+=1;
+                self.add_token(TokenType::Slash);
                 return;
             }
         }
 
         // handle whitespace
-        if c == b' ' || c == b'\t' || c == b'\r' {
+        if c == ' ' || c == '\t' || c == '\r' {
             self.ix += 1;
             return;
         }
-        if c == b'\n' {
+        if c == '\n' {
             self.line += 1;
             self.ix += 1;
+            self.line_start = self.ix;
             return;
         }
 
         // handle strings
-        if c == b'"' {
+        if c == '"' {
             self.scan_string();
             return;
         }
@@ -151,73 +188,169 @@ impl<'a> Scanner<'a> {
         }
 
 	    let punct_token = match c {
-			b'(' => TokenType::LeftParen,
-			b'{' => TokenType::LeftBrace,
-            b')' => TokenType::RightParen,
-			b'}' => TokenType::RightBrace,
-			b',' => TokenType::Comma,
-			b'.' => TokenType::Dot,
-			b'-' => TokenType::Minus,
-			b'+' => TokenType::Plus,
-			b';' => TokenType::Semicolon,
-			b'*' => TokenType::Star,
-			b'!' => {if self.match_next(b'=') {TokenType::BangEqual} else {TokenType::Bang}},
-			b'=' => {if self.match_next(b'=') {TokenType::EqualEqual} else {TokenType::Equal}},
-			b'<' => {if self.match_next(b'=') {TokenType::LessEqual} else {TokenType::Less}},
-			b'>' => {if self.match_next(b'=') {TokenType::GreaterEqual} else {TokenType::Greater}},
-			_ => TokenType::Unknown,
+			'(' => TokenType::LeftParen,
+			'{' => TokenType::LeftBrace,
+            ')' => TokenType::RightParen,
+			'}' => TokenType::RightBrace,
+			',' => TokenType::Comma,
+			'.' => TokenType::Dot,
+			'-' => TokenType::Minus,
+			'+' => TokenType::Plus,
+			';' => TokenType::Semicolon,
+			'*' => TokenType::Star,
+			'!' => {if self.match_next('=') {TokenType::BangEqual} else {TokenType::Bang}},
+			'=' => {if self.match_next('=') {TokenType::EqualEqual} else {TokenType::Equal}},
+			'<' => {if self.match_next('=') {TokenType::LessEqual} else {TokenType::Less}},
+			'>' => {if self.match_next('=') {TokenType::GreaterEqual} else {TokenType::Greater}},
+			_ => {
+                self.ix += 1;
+                let message = format!("Unexpected character '{}'.", c);
+                self.error(&message);
+                return;
+            },
 	    };
-        self.add_token(punct_token);
         self.ix += 1;
+        self.add_token(punct_token);
 	}
 
-    fn this_char(&self) -> u8 {
-        if self.ix >= self.source_text.len() {return b'\0'; }
-        return self.source_text.as_bytes()[self.ix];
+    fn this_char(&self) -> char {
+        if self.ix >= self.source_text.len() {return '\0'; }
+        return self.source_text[self.ix];
+    }
+
+    fn peek(&self) -> char {
+        if self.ix + 1 >= self.source_text.len() { return '\0'; }
+        return self.source_text[self.ix + 1];
     }
 
-    fn peek(&self) -> u8 {
-        if self.ix + 1 >= self.source_text.len() { return b'\0'; }
-        return self.source_text.as_bytes()[self.ix + 1];
+    fn text(&self, text_start: usize, text_end: usize) -> String {
+        self.source_text[text_start..text_end].iter().collect()
     }
 
 	fn add_token(&mut self, token_type: TokenType) {
         self.add_token_literal(token_type, "");
 	}
 
-    fn add_token_literal(&mut self, token_type: TokenType, literal: &'a str) {
-        let text_start = self.start;
-        let text_end = self.ix;
-        let text = &self.source_text[text_start..text_end];
-        self.tokens.push(Token::new(token_type, text, literal, self.line));
+    fn add_token_literal(&mut self, token_type: TokenType, literal: &str) {
+        let text = self.text(self.start, self.ix);
+        self.tokens.push(Token {
+            token_type: token_type,
+            lexeme: self.interner.intern(&text),
+            literal: self.interner.intern(literal),
+            line: self.start_line,
+            column: self.start_column,
+        });
     }
 
-	fn match_next(&mut self, match_char: u8) -> bool {
+	fn match_next(&mut self, match_char: char) -> bool {
 		if self.peek() != match_char { return false; }
 
 		self.ix += 1;
 		return true;
 	}
 
+    fn error(&mut self, message: &str) {
+        let lexeme = self.text(self.start, self.ix);
+        self.errors.push(LoxError::lexical(self.start_line, self.start_column, &lexeme, message));
+    }
+
+    // for errors partway through a token, on the current line
+    fn error_at(&mut self, error_start: usize, lexeme: &str, message: &str) {
+        let column = error_start - self.line_start + 1;
+        self.errors.push(LoxError::lexical(self.line, column, lexeme, message));
+    }
+
+    // escape sequences are decoded here, so the token's literal holds
+    // the string's actual contents
     fn scan_string(&mut self) {
-        while self.peek() != b'"' && self.peek() != b'\0' {
-            if self.peek() == b'\n' { self.line += 1; }
+        // opening "
+        self.ix += 1;
+
+        let mut literal = String::new();
+        loop {
+            if self.ix >= self.source_text.len() {
+                self.error("Unterminated string.");
+                return;
+            }
+
+            let c = self.this_char();
             self.ix += 1;
+            match c {
+                '"' => { break; },
+                '\\' => {
+                    if let Some(escaped) = self.scan_escape() {
+                        literal.push(escaped);
+                    }
+                },
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = self.ix;
+                    literal.push(c);
+                },
+                _ => { literal.push(c); },
+            }
+        }
+
+        self.add_token_literal(TokenType::StringLiteral, &literal);
+    }
+
+    // ix is just past the backslash
+    fn scan_escape(&mut self) -> Option<char> {
+        let escape_start = self.ix - 1;
+        let escaped = match self.this_char() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '"' => Some('"'),
+            '\\' => Some('\\'),
+            'u' => { return self.scan_unicode_escape(escape_start); },
+            _ => None,
+        };
+
+        if self.ix < self.source_text.len() && self.this_char() != '\n' {
+            self.ix += 1;
+        }
+        if escaped.is_none() {
+            let sequence = self.text(escape_start, self.ix);
+            let message = format!("Invalid escape sequence '{}'.", sequence);
+            self.error_at(escape_start, &sequence, &message);
         }
+        escaped
+    }
 
-        // closing "
-        self.ix += 2;
+    // \u{...} takes one to six hex digits naming a Unicode scalar value
+    fn scan_unicode_escape(&mut self, escape_start: usize) -> Option<char> {
+        // the u
+        self.ix += 1;
 
-        let lit_start = self.start + 1;
-        let lit_end = self.ix - 1; 
-        self.add_token_literal(TokenType::StringLiteral, &self.source_text[lit_start..lit_end]);
+        let mut code_point = None;
+        if self.this_char() == '{' {
+            self.ix += 1;
+            let digits_start = self.ix;
+            while self.this_char().is_ascii_hexdigit() {
+                self.ix += 1;
+            }
+            let digits = self.text(digits_start, self.ix);
+            if self.this_char() == '}' && !digits.is_empty() && digits.len() <= 6 {
+                self.ix += 1;
+                code_point = u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32);
+            }
+        }
+
+        if code_point.is_none() {
+            let sequence = self.text(escape_start, self.ix);
+            let message = format!("Invalid unicode escape '{}'.", sequence);
+            self.error_at(escape_start, &sequence, &message);
+        }
+        code_point
     }
 
     fn scan_number(&mut self) {
         while is_digit(self.this_char()) {
             self.ix += 1;
         }
-        if self.this_char() == b'.' && is_digit(self.peek()) {
+        if self.this_char() == '.' && is_digit(self.peek()) {
             self.ix += 1;
 
             while is_digit(self.this_char()) {
@@ -225,32 +358,30 @@ impl<'a> Scanner<'a> {
             }
         }
 
-        let num_start = self.start;
-        let num_end = self.ix;
-        self.add_token_literal(TokenType::Number, &self.source_text[num_start..num_end]);
+        let literal = self.text(self.start, self.ix);
+        self.add_token_literal(TokenType::Number, &literal);
     }
 
     fn scan_identifier(&mut self) {
         while is_alphanumeric(self.this_char()) {
             self.ix += 1;
         }
-        let id_start = self.start;
-        let id_end = self.ix;
-        self.add_token(keyword_to_token(&self.source_text[id_start..id_end]));
+        let word = self.text(self.start, self.ix);
+        self.add_token(keyword_to_token(&word));
     }
 
 }
 
-fn is_digit(c: u8) -> bool {
-    c >= b'0' && c <= b'9'
+// numbers stay ASCII-only
+fn is_digit(c: char) -> bool {
+    c >= '0' && c <= '9'
 }
 
-fn is_alpha(c: u8) -> bool {
-    (c >= b'a' && c <= b'z') ||
-        (c >= b'A' && c <= b'Z') ||
-        c == b'_'
+// identifiers follow Unicode's XID rules, plus a leading underscore
+fn is_alpha(c: char) -> bool {
+    c == '_' || UnicodeXID::is_xid_start(c)
 }
 
-fn is_alphanumeric(c: u8) -> bool {
-    is_digit(c) || is_alpha(c)
-}
+fn is_alphanumeric(c: char) -> bool {
+    UnicodeXID::is_xid_continue(c)
+}
\ No newline at end of file
@@ -0,0 +1,674 @@
+use chunk::{Chunk, OpCode};
+use compiler::Compiler;
+use debug;
+use error::{LoxError, StackFrame};
+use heap::{Heap, ObjRef};
+use host;
+use object::{BoundMethod, Class, Closure, Instance, Native, NativeFn, Obj, Upvalue, Value};
+use parse::Statement;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// the script's own frame plus as many nested calls as the tree-walker allows
+const FRAMES_MAX: usize = 256;
+
+// what a call instruction found in the callee slot
+enum Callee {
+	Closure,
+	Native(usize, NativeFn),
+	Class(Option<ObjRef>),
+	BoundMethod(Value, ObjRef),
+}
+
+struct CallFrame {
+	closure: ObjRef,
+	chunk: Rc<Chunk>,
+	ip: usize,
+	// stack index of slot zero
+	slots: usize,
+}
+
+// A stack-based virtual machine running the bytecode from `Compiler`.
+// Runs the same programs as `Interpreter`, with the same output and errors.
+pub struct Vm {
+	heap: Heap,
+	stack: Vec<Value>,
+	frames: Vec<CallFrame>,
+	// keyed by interned name
+	globals: HashMap<ObjRef, Value>,
+	// upvalues still pointing into the stack, ordered by stack slot
+	open_upvalues: Vec<ObjRef>,
+	output: Box<dyn Write>,
+	// looked up on every class call, so interned once up front
+	init_string: ObjRef,
+	// debugging aids, written to the output alongside the program's own
+	disassemble: bool,
+	trace: bool,
+}
+
+impl Vm {
+	pub fn new() -> Self {
+		Vm::with_output(Box::new(io::stdout()))
+	}
+
+	pub fn with_output(output: Box<dyn Write>) -> Self {
+		let mut heap = Heap::new();
+		let init_string = heap.intern("init");
+		let mut vm = Vm {
+			heap,
+			stack: vec![],
+			frames: vec![],
+			globals: HashMap::new(),
+			open_upvalues: vec![],
+			output,
+			init_string,
+			disassemble: false,
+			trace: false,
+		};
+		vm.define_native("clock", 0, clock_native);
+		vm
+	}
+
+	/// Lists the bytecode of every function in each program before it runs.
+	pub fn set_disassemble(&mut self, disassemble: bool) {
+		self.disassemble = disassemble;
+	}
+
+	/// Shows the value stack and the next instruction before each one executes.
+	pub fn set_trace(&mut self, trace: bool) {
+		self.trace = trace;
+	}
+
+	/// Collects garbage before every allocation instead of as the heap grows.
+	/// Slow, but any object the VM forgot to root is freed straight away.
+	pub fn set_gc_stress(&mut self, stress: bool) {
+		self.heap.stress = stress;
+	}
+
+	/// The number of objects currently on the heap, reachable or not.
+	/// Garbage, cycles included, is reclaimed as the heap grows:
+	///
+	/// ```
+	/// let mut vm = rustlox::Vm::new();
+	/// let names = rustlox::Interner::new();
+	/// let program = rustlox::parse_program("
+	///     class Node { init() { this.next = this; } }
+	///     for (var i = 0; i < 100000; i = i + 1) { Node(); }
+	/// ", &names).unwrap();
+	/// vm.interpret(&program).unwrap();
+	/// assert!(vm.live_objects() < 100000);
+	/// ```
+	pub fn live_objects(&self) -> usize {
+		self.heap.live_objects()
+	}
+
+	fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+		let native = self.heap.alloc(Obj::Native(Native { name: name.to_string(), arity, function }));
+		let name = self.heap.intern(name);
+		self.globals.insert(name, Value::Obj(native));
+	}
+
+	/// Compiles a resolved program and runs it. Globals persist between calls.
+	///
+	/// ```
+	/// let mut vm = rustlox::Vm::new();
+	/// let names = rustlox::Interner::new();
+	/// let program = rustlox::parse_program("var a = 1;", &names).unwrap();
+	/// vm.interpret(&program).unwrap();
+	/// let program = rustlox::parse_program("print b;", &names).unwrap();
+	/// assert!(vm.interpret(&program).is_err());
+	/// ```
+	pub fn interpret(&mut self, program: &[Statement]) -> Result<(), LoxError> {
+		let function = Compiler::new(&mut self.heap).compile(program)?;
+		if self.disassemble {
+			let listing = debug::disassemble_function(&self.heap, function);
+			if let Err(message) = host::write_output(&mut *self.output, format_args!("{}", listing)) {
+				// nothing has run yet, so the error is placed at the start of the script
+				let start = self.heap.function(function).chunk.token(0).clone();
+				return Err(LoxError::runtime(&start, &message));
+			}
+		}
+		// kept on the stack while its closure is allocated, which may collect
+		self.push(Value::Obj(function));
+		let closure = self.alloc(Obj::Closure(Closure { function, upvalues: vec![] }));
+		self.pop();
+		self.push(Value::Obj(closure));
+		self.call_closure(closure, 0)?;
+
+		let result = self.run();
+		if result.is_err() {
+			// closures that escaped keep the values they captured, as they
+			// would had the frames returned normally
+			self.close_upvalues(0);
+			self.stack.clear();
+			self.frames.clear();
+			self.open_upvalues.clear();
+		}
+		result
+	}
+
+	fn run(&mut self) -> Result<(), LoxError> {
+		loop {
+			let traced = if self.trace { self.trace_instruction() } else { Ok(()) };
+			let byte = self.read_byte();
+			// reported at the instruction that was about to run
+			if let Err(message) = traced {
+				return Err(self.runtime_error(&message));
+			}
+			let op = OpCode::from_byte(byte).expect("invalid opcode");
+			match op {
+				OpCode::Constant => {
+					let constant = self.read_constant();
+					self.push(constant);
+				},
+				OpCode::Nil => self.push(Value::Nil),
+				OpCode::True => self.push(Value::Boolean(true)),
+				OpCode::False => self.push(Value::Boolean(false)),
+				OpCode::Pop => { self.pop(); },
+				OpCode::GetLocal => {
+					let slot = self.frame().slots + self.read_byte() as usize;
+					let value = self.stack[slot];
+					self.push(value);
+				},
+				OpCode::SetLocal => {
+					let slot = self.frame().slots + self.read_byte() as usize;
+					self.stack[slot] = self.peek(0);
+				},
+				OpCode::GetGlobal => {
+					let name = self.read_string();
+					match self.globals.get(&name) {
+						Some(&value) => self.push(value),
+						None => return Err(self.runtime_error(&format!("Undefined variable '{}'.", self.heap.string(name)))),
+					}
+				},
+				OpCode::DefineGlobal => {
+					let name = self.read_string();
+					let value = self.pop();
+					self.globals.insert(name, value);
+				},
+				OpCode::SetGlobal => {
+					let name = self.read_string();
+					let value = self.peek(0);
+					match self.globals.get_mut(&name) {
+						Some(global) => *global = value,
+						None => return Err(self.runtime_error(&format!("Undefined variable '{}'.", self.heap.string(name)))),
+					}
+				},
+				OpCode::GetUpvalue => {
+					let index = self.read_byte() as usize;
+					let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+					let value = match *self.heap.upvalue_mut(upvalue) {
+						Upvalue::Open(slot) => self.stack[slot],
+						Upvalue::Closed(value) => value,
+					};
+					self.push(value);
+				},
+				OpCode::SetUpvalue => {
+					let index = self.read_byte() as usize;
+					let upvalue = self.heap.closure(self.frame().closure).upvalues[index];
+					let value = self.peek(0);
+					match *self.heap.upvalue_mut(upvalue) {
+						Upvalue::Open(slot) => self.stack[slot] = value,
+						Upvalue::Closed(ref mut closed) => *closed = value,
+					}
+				},
+				OpCode::GetProperty => {
+					let name = self.read_string();
+					let instance = match self.peek(0) {
+						Value::Obj(obj_ref) => match *self.heap.get(obj_ref) {
+							Obj::Instance(_) => obj_ref,
+							_ => return Err(self.runtime_error("Only instances have properties.")),
+						},
+						_ => return Err(self.runtime_error("Only instances have properties.")),
+					};
+					// fields shadow methods
+					let field = match *self.heap.get(instance) {
+						Obj::Instance(ref instance) => instance.fields.get(&name).cloned(),
+						_ => None,
+					};
+					match field {
+						Some(value) => {
+							self.pop();
+							self.push(value);
+						},
+						None => {
+							let class = self.class_of(instance);
+							self.bind_method(class, name)?;
+						},
+					}
+				},
+				OpCode::SetProperty => {
+					let name = self.read_string();
+					let value = self.peek(0);
+					let instance = match self.peek(1) {
+						Value::Obj(obj_ref) => obj_ref,
+						_ => return Err(self.runtime_error("Only instances have fields.")),
+					};
+					match *self.heap.get_mut(instance) {
+						Obj::Instance(ref mut instance) => { instance.fields.insert(name, value); },
+						_ => return Err(self.runtime_error("Only instances have fields.")),
+					}
+					// leave the assigned value as the expression's result
+					self.pop();
+					self.pop();
+					self.push(value);
+				},
+				OpCode::GetSuper => {
+					let name = self.read_string();
+					let superclass = match self.pop() {
+						Value::Obj(obj_ref) => obj_ref,
+						_ => panic!("superclass is not an object"),
+					};
+					self.bind_method(superclass, name)?;
+				},
+				OpCode::Equal => {
+					let b = self.pop();
+					let a = self.pop();
+					// interning makes equal strings the same object
+					self.push(Value::Boolean(a == b));
+				},
+				OpCode::Greater => self.binary_number_op(|a, b| Value::Boolean(a > b))?,
+				OpCode::Less => self.binary_number_op(|a, b| Value::Boolean(a < b))?,
+				OpCode::Add => {
+					let (a, b) = (self.peek(1), self.peek(0));
+					if let (Value::Number(a), Value::Number(b)) = (a, b) {
+						self.pop();
+						self.pop();
+						self.push(Value::Number(a + b));
+					} else {
+						let concatenated = match (self.heap.as_string(a), self.heap.as_string(b)) {
+							(Some(a), Some(b)) => a.to_string() + b,
+							_ => return Err(self.runtime_error("Operands must be two numbers or two strings.")),
+						};
+						self.pop();
+						self.pop();
+						let string = self.intern(&concatenated);
+						self.push(Value::Obj(string));
+					}
+				},
+				OpCode::Subtract => self.binary_number_op(|a, b| Value::Number(a - b))?,
+				OpCode::Multiply => self.binary_number_op(|a, b| Value::Number(a * b))?,
+				OpCode::Divide => self.binary_number_op(|a, b| Value::Number(a / b))?,
+				OpCode::Not => {
+					let value = self.pop();
+					self.push(Value::Boolean(!is_truthy(value)));
+				},
+				OpCode::Negate => {
+					match self.peek(0) {
+						Value::Number(n) => {
+							self.pop();
+							self.push(Value::Number(-n));
+						},
+						_ => return Err(self.runtime_error("Operand must be a number.")),
+					}
+				},
+				OpCode::Print => {
+					let value = self.pop();
+					let text = self.heap.format(value);
+					if let Err(message) = host::write_output(&mut *self.output, format_args!("{}\n", text)) {
+						return Err(self.runtime_error(&message));
+					}
+				},
+				OpCode::Jump => {
+					let offset = self.read_short() as usize;
+					self.frame_mut().ip += offset;
+				},
+				OpCode::JumpIfFalse => {
+					let offset = self.read_short() as usize;
+					if !is_truthy(self.peek(0)) {
+						self.frame_mut().ip += offset;
+					}
+				},
+				OpCode::Loop => {
+					let offset = self.read_short() as usize;
+					self.frame_mut().ip -= offset;
+				},
+				OpCode::Call => {
+					let arg_count = self.read_byte() as usize;
+					let callee = self.peek(arg_count);
+					self.call_value(callee, arg_count)?;
+				},
+				OpCode::Closure => {
+					let function = match self.read_constant() {
+						Value::Obj(obj_ref) => obj_ref,
+						_ => panic!("closure constant is not a function"),
+					};
+					let upvalue_count = self.heap.function(function).upvalue_count;
+					let mut upvalues = Vec::with_capacity(upvalue_count);
+					for _ in 0..upvalue_count {
+						let is_local = self.read_byte() == 1;
+						let index = self.read_byte() as usize;
+						if is_local {
+							let slot = self.frame().slots + index;
+							upvalues.push(self.capture_upvalue(slot));
+						} else {
+							upvalues.push(self.heap.closure(self.frame().closure).upvalues[index]);
+						}
+					}
+					let closure = self.alloc(Obj::Closure(Closure { function, upvalues }));
+					self.push(Value::Obj(closure));
+				},
+				OpCode::CloseUpvalue => {
+					let top = self.stack.len() - 1;
+					self.close_upvalues(top);
+					self.pop();
+				},
+				OpCode::Return => {
+					let result = self.pop();
+					let frame = self.frames.pop().expect("no frame to return from");
+					self.close_upvalues(frame.slots);
+					self.stack.truncate(frame.slots);
+					if self.frames.is_empty() {
+						return Ok(());
+					}
+					self.push(result);
+				},
+				OpCode::Class => {
+					let name = self.read_string();
+					let class = self.alloc(Obj::Class(Class { name, methods: HashMap::new() }));
+					self.push(Value::Obj(class));
+				},
+				OpCode::Inherit => {
+					let superclass = match self.peek(1) {
+						Value::Obj(obj_ref) => match *self.heap.get(obj_ref) {
+							Obj::Class(ref class) => Some(class.methods.clone()),
+							_ => None,
+						},
+						_ => None,
+					};
+					let methods = match superclass {
+						Some(methods) => methods,
+						None => return Err(self.runtime_error("Superclass must be a class.")),
+					};
+					// methods are copied down now; the subclass's own
+					// definitions come later and override them
+					let subclass = self.pop_obj();
+					self.heap.class_mut(subclass).methods.extend(methods);
+				},
+				OpCode::Method => {
+					let name = self.read_string();
+					let method = self.pop_obj();
+					let class = match self.peek(0) {
+						Value::Obj(obj_ref) => obj_ref,
+						_ => panic!("method defined outside a class"),
+					};
+					self.heap.class_mut(class).methods.insert(name, method);
+				},
+			}
+		}
+	}
+
+	// memory
+
+	// every allocation while the program runs goes through here, so anything
+	// not yet reachable from a root must be kept on the stack across it
+	fn alloc(&mut self, obj: Obj) -> ObjRef {
+		if self.heap.should_collect() {
+			self.collect_garbage();
+		}
+		self.heap.alloc(obj)
+	}
+
+	fn intern(&mut self, s: &str) -> ObjRef {
+		match self.heap.find_string(s) {
+			Some(string) => string,
+			None => {
+				if self.heap.should_collect() {
+					self.collect_garbage();
+				}
+				self.heap.intern(s)
+			},
+		}
+	}
+
+	fn collect_garbage(&mut self) {
+		for &value in &self.stack {
+			self.heap.mark_value(value);
+		}
+		// a bound method's closure is not on the stack, only in its frame
+		for frame in &self.frames {
+			self.heap.mark_object(frame.closure);
+		}
+		for &upvalue in &self.open_upvalues {
+			self.heap.mark_object(upvalue);
+		}
+		for (&name, &value) in &self.globals {
+			self.heap.mark_object(name);
+			self.heap.mark_value(value);
+		}
+		self.heap.mark_object(self.init_string);
+		self.heap.collect();
+	}
+
+	fn trace_instruction(&mut self) -> Result<(), String> {
+		let mut stack = "          ".to_string();
+		for &value in &self.stack {
+			stack.push_str(&format!("[ {} ]", self.heap.format(value)));
+		}
+		let (instruction, _) = {
+			let frame = self.frame();
+			debug::disassemble_instruction(&self.heap, &frame.chunk, frame.ip)
+		};
+		host::write_output(&mut *self.output, format_args!("{}\n{}\n", stack, instruction))
+	}
+
+	// calls
+
+	fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), LoxError> {
+		let obj_ref = match callee {
+			Value::Obj(obj_ref) => obj_ref,
+			_ => return Err(self.runtime_error("Can only call functions and classes.")),
+		};
+		let callee_slot = self.stack.len() - arg_count - 1;
+		let callee = match *self.heap.get(obj_ref) {
+			Obj::Closure(_) => Callee::Closure,
+			Obj::Native(ref native) => Callee::Native(native.arity, native.function),
+			Obj::Class(ref class) => Callee::Class(class.methods.get(&self.init_string).cloned()),
+			Obj::BoundMethod(ref bound) => Callee::BoundMethod(bound.receiver, bound.method),
+			_ => return Err(self.runtime_error("Can only call functions and classes.")),
+		};
+		match callee {
+			Callee::Closure => self.call_closure(obj_ref, arg_count),
+			Callee::Native(arity, function) => {
+				self.check_arity(arity, arg_count)?;
+				let result = function(&self.stack[callee_slot + 1..]);
+				let result = result.map_err(|message| self.runtime_error(&message))?;
+				self.stack.truncate(callee_slot);
+				self.push(result);
+				Ok(())
+			},
+			Callee::Class(initializer) => {
+				let instance = self.alloc(Obj::Instance(Instance { class: obj_ref, fields: HashMap::new() }));
+				// the instance takes the class's slot, becoming the initializer's `this`
+				self.stack[callee_slot] = Value::Obj(instance);
+				match initializer {
+					Some(initializer) => self.call_closure(initializer, arg_count),
+					None => self.check_arity(0, arg_count),
+				}
+			},
+			Callee::BoundMethod(receiver, method) => {
+				self.stack[callee_slot] = receiver;
+				self.call_closure(method, arg_count)
+			},
+		}
+	}
+
+	fn call_closure(&mut self, closure: ObjRef, arg_count: usize) -> Result<(), LoxError> {
+		let function = self.heap.closure(closure).function;
+		let (arity, chunk) = {
+			let function = self.heap.function(function);
+			(function.arity, function.chunk.clone())
+		};
+		self.check_arity(arity, arg_count)?;
+		if self.frames.len() >= FRAMES_MAX {
+			return Err(self.runtime_error("Stack overflow."));
+		}
+		let slots = self.stack.len() - arg_count - 1;
+		self.frames.push(CallFrame { closure, chunk, ip: 0, slots });
+		Ok(())
+	}
+
+	fn check_arity(&self, arity: usize, arg_count: usize) -> Result<(), LoxError> {
+		if arity != arg_count {
+			let message = format!("Expected {} arguments but got {}.", arity, arg_count);
+			return Err(self.runtime_error(&message));
+		}
+		Ok(())
+	}
+
+	// replaces the instance on top of the stack with the named method bound to it
+	fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<(), LoxError> {
+		let method = match self.heap.class(class).methods.get(&name) {
+			Some(&method) => method,
+			None => return Err(self.runtime_error(&format!("Undefined property '{}'.", self.heap.string(name)))),
+		};
+		// the receiver stays on the stack until the bound method holds it
+		let receiver = self.peek(0);
+		let bound = self.alloc(Obj::BoundMethod(BoundMethod { receiver, method }));
+		self.pop();
+		self.push(Value::Obj(bound));
+		Ok(())
+	}
+
+	fn class_of(&self, instance: ObjRef) -> ObjRef {
+		match *self.heap.get(instance) {
+			Obj::Instance(ref instance) => instance.class,
+			_ => panic!("expected an instance object"),
+		}
+	}
+
+	// upvalues
+
+	// reuses the open upvalue for a slot if one exists, so closures
+	// capturing the same variable share it
+	fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+		let mut insert_at = self.open_upvalues.len();
+		for (i, &upvalue) in self.open_upvalues.iter().enumerate().rev() {
+			match *self.heap.upvalue_mut(upvalue) {
+				Upvalue::Open(open_slot) if open_slot == slot => return upvalue,
+				Upvalue::Open(open_slot) if open_slot < slot => break,
+				_ => insert_at = i,
+			}
+		}
+		let upvalue = self.alloc(Obj::Upvalue(Upvalue::Open(slot)));
+		self.open_upvalues.insert(insert_at, upvalue);
+		upvalue
+	}
+
+	// moves every captured variable at or above `last` off the stack
+	fn close_upvalues(&mut self, last: usize) {
+		while let Some(&upvalue) = self.open_upvalues.last() {
+			let slot = match *self.heap.upvalue_mut(upvalue) {
+				Upvalue::Open(slot) => slot,
+				Upvalue::Closed(_) => panic!("closed upvalue left in the open list"),
+			};
+			if slot < last {
+				break;
+			}
+			*self.heap.upvalue_mut(upvalue) = Upvalue::Closed(self.stack[slot]);
+			self.open_upvalues.pop();
+		}
+	}
+
+	// reading the code stream
+
+	fn frame(&self) -> &CallFrame {
+		self.frames.last().expect("no active call frame")
+	}
+
+	fn frame_mut(&mut self) -> &mut CallFrame {
+		self.frames.last_mut().expect("no active call frame")
+	}
+
+	fn read_byte(&mut self) -> u8 {
+		let frame = self.frame_mut();
+		let byte = frame.chunk.code[frame.ip];
+		frame.ip += 1;
+		byte
+	}
+
+	fn read_short(&mut self) -> u16 {
+		let frame = self.frame_mut();
+		let short = frame.chunk.read_short(frame.ip);
+		frame.ip += 2;
+		short
+	}
+
+	fn read_constant(&mut self) -> Value {
+		let index = self.read_short() as usize;
+		self.frame().chunk.constants[index]
+	}
+
+	// names are interned string constants, used as they are for lookups
+	fn read_string(&mut self) -> ObjRef {
+		match self.read_constant() {
+			Value::Obj(obj_ref) => obj_ref,
+			_ => panic!("name constant is not a string"),
+		}
+	}
+
+	// the stack
+
+	fn push(&mut self, value: Value) {
+		self.stack.push(value);
+	}
+
+	fn pop(&mut self) -> Value {
+		self.stack.pop().expect("stack underflow")
+	}
+
+	fn pop_obj(&mut self) -> ObjRef {
+		match self.pop() {
+			Value::Obj(obj_ref) => obj_ref,
+			_ => panic!("expected an object on the stack"),
+		}
+	}
+
+	fn peek(&self, distance: usize) -> Value {
+		self.stack[self.stack.len() - 1 - distance]
+	}
+
+	fn binary_number_op<F>(&mut self, op: F) -> Result<(), LoxError>
+		where F: Fn(f64, f64) -> Value {
+		match (self.peek(1), self.peek(0)) {
+			(Value::Number(a), Value::Number(b)) => {
+				self.pop();
+				self.pop();
+				self.push(op(a, b));
+				Ok(())
+			},
+			_ => Err(self.runtime_error("Operands must be numbers.")),
+		}
+	}
+
+	// errors are reported at the token the current instruction was compiled
+	// from, with a trace of every active frame, innermost first
+	fn runtime_error(&self, message: &str) -> LoxError {
+		let frame = self.frame();
+		let mut error = LoxError::runtime(frame.chunk.token(frame.ip - 1), message);
+		for (depth, frame) in self.frames.iter().enumerate().rev() {
+			let mut function = None;
+			if depth > 0 {
+				let closure = self.heap.closure(frame.closure);
+				function = Some(self.heap.function(closure.function).name.clone());
+			}
+			error.trace.push(StackFrame { function, line: frame.chunk.line(frame.ip - 1) });
+		}
+		error
+	}
+}
+
+impl Default for Vm {
+	fn default() -> Self {
+		Vm::new()
+	}
+}
+
+fn is_truthy(value: Value) -> bool {
+	!matches!(value, Value::Nil | Value::Boolean(false))
+}
+
+fn clock_native(_args: &[Value]) -> Result<Value, String> {
+	host::clock().map(Value::Number)
+}
@@ -0,0 +1,40 @@
+// Shared by the integration tests that check what Lox programs print. Not
+// every test uses all of it.
+#![allow(dead_code)]
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+// an output sink whose contents can still be read after it's handed over
+#[derive(Clone, Default)]
+pub struct Captured(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Captured {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.borrow_mut().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Captured {
+	pub fn text(&self) -> String {
+		String::from_utf8(self.0.borrow().clone()).unwrap()
+	}
+}
+
+// an output sink that has gone away, like a closed pipe
+pub struct Closed;
+
+impl Write for Closed {
+	fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+		Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken pipe"))
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
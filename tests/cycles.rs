@@ -0,0 +1,44 @@
+// The tree-walker frees cycles of scopes and instances once they can't be
+// reached, and must keep every one that still can.
+
+extern crate rustlox;
+
+use rustlox::{parse_program, Interpreter, Value};
+
+fn run(interpreter: &mut Interpreter, source: &str) -> Value {
+	let program = parse_program(source, interpreter.interner()).unwrap();
+	interpreter.interpret(&program).unwrap()
+}
+
+#[test]
+fn unreachable_cycles_are_freed() {
+	let mut interpreter = Interpreter::new();
+	run(&mut interpreter, "
+		fun f() { fun g() { return 1; } return g(); }
+		class A { init() { this.self = this; } }
+		for (var i = 0; i < 20000; i = i + 1) { f(); A(); }
+	");
+	assert!(interpreter.live_objects() < 5000);
+}
+
+#[test]
+fn reachable_cycles_survive_collection() {
+	let mut interpreter = Interpreter::new();
+	run(&mut interpreter, "
+		fun counter() {
+			var count = 0;
+			fun increment() { count = count + 1; return count; }
+			return increment;
+		}
+		class Node {
+			init(value) { this.value = value; this.self = this; }
+			get() { return this.self.value; }
+		}
+		var next = counter();
+		var node = Node(41);
+		var method = node.get;
+		for (var i = 0; i < 20000; i = i + 1) { counter()(); Node(i); }
+	");
+	assert_eq!(run(&mut interpreter, "next() + next();"), Value::Number(3.0));
+	assert_eq!(run(&mut interpreter, "method() + node.self.get();"), Value::Number(82.0));
+}
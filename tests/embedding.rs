@@ -0,0 +1,138 @@
+// The library API, as a Rust program embedding Lox would use it.
+
+extern crate rustlox;
+
+mod common;
+
+use common::{Captured, Closed};
+use rustlox::{ErrorKind, Lox, LoxError, Value};
+use std::error::Error;
+use std::io;
+use std::thread;
+
+#[test]
+fn globals_persist_between_runs() {
+	let mut lox = Lox::new();
+	lox.run_source("var count = 1; fun bump() { count = count + 1; }").unwrap();
+	lox.run_source("bump(); bump();").unwrap();
+	assert_eq!(lox.run_source("count;").unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn a_run_gives_back_its_final_expression() {
+	let mut lox = Lox::new();
+	assert_eq!(lox.run_source("1 + 2;").unwrap(), Value::Number(3.0));
+	assert_eq!(lox.run_source("var a = 1;").unwrap(), Value::Nil);
+	assert_eq!(lox.run_source("\"a\" + \"b\";").unwrap(), Value::StringLiteral("ab".into()));
+}
+
+#[test]
+fn strings_from_the_host_equal_strings_from_lox() {
+	let mut lox = Lox::new();
+	lox.define_native("greeting", 0, |_| Ok(Value::StringLiteral("hi".into())));
+	let from_lox = lox.run_source("\"hi\";").unwrap();
+	assert_eq!(from_lox, Value::StringLiteral("hi".into()));
+	assert_ne!(from_lox, Value::StringLiteral("ho".into()));
+	assert_eq!(lox.run_source("greeting() == \"hi\";").unwrap(), Value::Boolean(true));
+}
+
+#[test]
+fn errors_come_back_as_values() {
+	let mut lox = Lox::new();
+	let error = lox.run_source("nil + 1;").unwrap_err();
+	assert_eq!(error.kind, ErrorKind::Runtime);
+	assert_eq!(error.line, 1);
+
+	let errors = lox.run("var = 1; print;").unwrap_err();
+	assert_eq!(errors.len(), 2);
+	assert!(errors.iter().all(|e| e.kind == ErrorKind::Syntax));
+}
+
+#[test]
+fn errors_pass_through_the_question_mark_operator() {
+	fn run(source: &str) -> Result<Value, Box<dyn Error>> {
+		let mut lox = Lox::new();
+		Ok(lox.run_source(source)?)
+	}
+	let error = run("nil + 1;").unwrap_err();
+	assert!(error.to_string().starts_with("[line 1:5] Runtime error at '+':"));
+	assert_eq!(error.downcast_ref::<LoxError>().unwrap().kind, ErrorKind::Runtime);
+}
+
+#[test]
+fn natives_are_callable_from_lox() {
+	let mut lox = Lox::new();
+	lox.define_native("add", 2, |args| match (&args[0], &args[1]) {
+		(&Value::Number(a), &Value::Number(b)) => Ok(Value::Number(a + b)),
+		_ => Err("Arguments must be numbers.".to_string()),
+	});
+	lox.define_native("name", 0, |_| Ok(Value::StringLiteral("lox".into())));
+	assert_eq!(lox.run_source("add(1, 2);").unwrap(), Value::Number(3.0));
+	assert_eq!(lox.run_source("var f = add; f(3, 4);").unwrap(), Value::Number(7.0));
+	// strings a native makes are equal to the same text written in Lox
+	assert_eq!(lox.run_source("name() == \"lox\";").unwrap(), Value::Boolean(true));
+
+	let error = lox.run_source("add(1, nil);").unwrap_err();
+	assert_eq!(error.kind, ErrorKind::Runtime);
+	assert_eq!(error.message, "Arguments must be numbers.");
+	let error = lox.run_source("add(1);").unwrap_err();
+	assert_eq!(error.message, "Expected 2 arguments but got 1.");
+}
+
+#[test]
+fn print_writes_to_the_given_sink() {
+	let captured = Captured::default();
+	let mut lox = Lox::with_output(Box::new(captured.clone()));
+	lox.run_source("print 1; print \"two\";").unwrap();
+	lox.run_source("print nil;").unwrap();
+	assert_eq!(captured.text(), "1\ntwo\nnil\n");
+
+	// output already written stays written when a later statement fails
+	let captured = Captured::default();
+	let mut lox = Lox::with_output(Box::new(captured.clone()));
+	assert!(lox.run_source("print \"before\"; nil();").is_err());
+	assert_eq!(captured.text(), "before\n");
+}
+
+#[test]
+fn a_failed_write_is_a_runtime_error() {
+	let mut lox = Lox::with_output(Box::new(Closed));
+	let error = lox.run_source("var a = 1;\nprint a;").unwrap_err();
+	assert_eq!(error.to_string(), "[line 2:1] Runtime error at 'print': Could not write output: broken pipe.\n[line 2] in script");
+}
+
+#[test]
+fn output_can_be_discarded() {
+	let mut lox = Lox::with_output(Box::new(io::sink()));
+	assert_eq!(lox.run_source("print 1; 2;").unwrap(), Value::Number(2.0));
+}
+
+#[test]
+fn runaway_recursion_is_a_runtime_error() {
+	// runs on the test thread, whose stack is the default for a spawned
+	// thread, so the deepest calls allowed must fit in it even unoptimized
+	// and with bodies that nest
+	let mut lox = Lox::new();
+	let source = "class A { m(n) { while (true) { if (n > 0) { { return -(this.m(n + 1) * 2); } } } } }\nA().m(1);";
+	let error = lox.run_source(source).unwrap_err();
+	assert_eq!(error.message, "Stack overflow.");
+	// every frame, then the script
+	assert_eq!(error.trace.len(), 101);
+	assert_eq!(error.trace.last().unwrap().line, 2);
+}
+
+#[test]
+fn the_call_depth_can_be_changed() {
+	let source = "fun recurse(n) { return recurse(n + 1); }\nrecurse(0);";
+	let mut lox = Lox::new();
+	lox.set_max_call_depth(10);
+	assert_eq!(lox.run_source(source).unwrap_err().trace.len(), 11);
+
+	// a host with a bigger stack can allow deeper calls
+	let run = thread::Builder::new().stack_size(32 * 1024 * 1024).spawn(move || {
+		let mut lox = Lox::new();
+		lox.set_max_call_depth(1000);
+		lox.run_source(source).unwrap_err().trace.len()
+	});
+	assert_eq!(run.unwrap().join().unwrap(), 1001);
+}
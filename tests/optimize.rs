@@ -0,0 +1,34 @@
+// What the optimizer leaves of a program, printed back as Lox source.
+
+extern crate rustlox;
+
+use rustlox::{parse_program, printer, Interner, Optimizer};
+
+fn optimized(source: &str) -> String {
+	let interner = Interner::new();
+	let mut program = parse_program(source, &interner).unwrap();
+	Optimizer::new(&interner).optimize(&mut program);
+	printer::print_program(&program)
+}
+
+#[test]
+fn constant_expressions_are_folded() {
+	assert_eq!(optimized("print 2 * 3 + 1;"), "print 7;\n");
+	assert_eq!(optimized("print ((1 + 2));"), "print 3;\n");
+	assert_eq!(optimized("print !true;"), "print false;\n");
+	assert_eq!(optimized("print 1 < 2 and \"x\";"), "print \"x\";\n");
+	assert_eq!(optimized("print \"a\" + \"b\";"), "print \"ab\";\n");
+}
+
+#[test]
+fn branches_that_never_run_are_dropped() {
+	assert_eq!(optimized("if (false) print 1; else print 2;"), "print 2;\n");
+	assert_eq!(optimized("if (true) { print 1; } else { print 2; }"), "{\n  print 1;\n}\n");
+}
+
+#[test]
+fn expressions_that_fail_are_left_for_runtime() {
+	assert_eq!(optimized("print 1 + \"a\";"), "print 1 + \"a\";\n");
+	assert_eq!(optimized("print -\"a\";"), "print -\"a\";\n");
+	assert_eq!(optimized("print x * 2;"), "print x * 2;\n");
+}
@@ -0,0 +1,203 @@
+// Round trips through the printer: a tree printed as Lox source must parse
+// back to the same tree. Trees are generated in canonical form, with
+// groupings exactly where precedence needs them, since that is the form
+// the parser gives back for printed source.
+
+extern crate proptest;
+extern crate rustlox;
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use rustlox::parse::{Expression, FunctionDecl};
+use rustlox::scanner::{Token, TokenType};
+use rustlox::{printer, Interner, Parser, Scanner, Statement, Symbol};
+use std::rc::Rc;
+
+const NAMES: &[&str] = &["a", "b", "foo", "bar_2", "_x", "ifs", "orange"];
+
+// binding strength, loosest first, mirroring the grammar rather than the
+// printer, so the two are checked against each other
+const ASSIGNMENT: u8 = 0;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const EQUALITY: u8 = 3;
+const COMPARISON: u8 = 4;
+const TERM: u8 = 5;
+const FACTOR: u8 = 6;
+const UNARY: u8 = 7;
+const CALL: u8 = 8;
+const PRIMARY: u8 = 9;
+
+const BINARY_OPERATORS: &[(TokenType, &str, u8)] = &[
+	(TokenType::EqualEqual, "==", EQUALITY),
+	(TokenType::BangEqual, "!=", EQUALITY),
+	(TokenType::Less, "<", COMPARISON),
+	(TokenType::LessEqual, "<=", COMPARISON),
+	(TokenType::Greater, ">", COMPARISON),
+	(TokenType::GreaterEqual, ">=", COMPARISON),
+	(TokenType::Plus, "+", TERM),
+	(TokenType::Minus, "-", TERM),
+	(TokenType::Star, "*", FACTOR),
+	(TokenType::Slash, "/", FACTOR),
+];
+
+fn token(token_type: TokenType, lexeme: &str) -> Token {
+	Token::new(token_type, lexeme, "", 1, 1)
+}
+
+fn identifier(name: &str) -> Token {
+	token(TokenType::Identifier, name)
+}
+
+fn precedence(expr: &Expression) -> u8 {
+	match *expr {
+		Expression::Assign(..) | Expression::Set(..) => ASSIGNMENT,
+		Expression::Logical(_, ref operator, _) if operator.token_type() == TokenType::Or => OR,
+		Expression::Logical(..) => AND,
+		Expression::Binary(_, ref operator, _) => {
+			BINARY_OPERATORS.iter().find(|op| op.0 == operator.token_type()).unwrap().2
+		},
+		Expression::Unary(..) => UNARY,
+		Expression::Call(..) | Expression::Get(..) => CALL,
+		_ => PRIMARY,
+	}
+}
+
+// where the grammar wants an expression binding at least as tightly as
+// `min`, a looser one has to be written in parentheses
+fn operand(expr: Expression, min: u8) -> Box<Expression> {
+	if precedence(&expr) < min {
+		return Box::new(Expression::Grouping(Box::new(expr)));
+	}
+	Box::new(expr)
+}
+
+fn name() -> impl Strategy<Value = String> {
+	select(NAMES).prop_map(String::from)
+}
+
+fn leaf() -> impl Strategy<Value = Expression> {
+	prop_oneof![
+		(0u32..1000).prop_map(|n| Expression::Number(f64::from(n))),
+		(0.0f64..1e9).prop_map(Expression::Number),
+		"[ -~\t\n]{0,8}".prop_map(|s| Expression::Literal(Symbol::from(s))),
+		Just(Expression::True),
+		Just(Expression::False),
+		Just(Expression::Nil),
+		name().prop_map(|n| Expression::Variable(identifier(&n), None)),
+		Just(Expression::This(token(TokenType::This, "this"), None)),
+		name().prop_map(|n| Expression::Super(token(TokenType::Super, "super"), identifier(&n), None)),
+	]
+}
+
+fn expression() -> impl Strategy<Value = Expression> {
+	leaf().prop_recursive(5, 48, 4, |inner| {
+		prop_oneof![
+			(select(vec!["-", "!"]), inner.clone()).prop_map(|(op, e)| {
+				let token_type = if op == "-" { TokenType::Minus } else { TokenType::Bang };
+				Expression::Unary(token(token_type, op), operand(e, UNARY))
+			}),
+			(inner.clone(), select(BINARY_OPERATORS), inner.clone()).prop_map(|(l, (token_type, op, level), r)| {
+				Expression::Binary(operand(l, level), token(token_type, op), operand(r, level + 1))
+			}),
+			(inner.clone(), any::<bool>(), inner.clone()).prop_map(|(l, is_or, r)| {
+				let (token_type, op, level) = if is_or { (TokenType::Or, "or", OR) } else { (TokenType::And, "and", AND) };
+				Expression::Logical(operand(l, level), token(token_type, op), operand(r, level + 1))
+			}),
+			(name(), inner.clone()).prop_map(|(n, value)| {
+				Expression::Assign(identifier(&n), Box::new(value), None)
+			}),
+			(inner.clone(), prop::collection::vec(inner.clone(), 0..3)).prop_map(|(callee, arguments)| {
+				Expression::Call(operand(callee, CALL), token(TokenType::RightParen, ")"), arguments)
+			}),
+			(inner.clone(), name()).prop_map(|(object, n)| {
+				Expression::Get(operand(object, CALL), identifier(&n))
+			}),
+			(inner.clone(), name(), inner.clone()).prop_map(|(object, n, value)| {
+				Expression::Set(operand(object, CALL), identifier(&n), Box::new(value))
+			}),
+		]
+	})
+}
+
+fn function() -> impl Strategy<Value = FunctionDecl> {
+	let body_item = prop_oneof![simple_statement(), var_declaration()];
+	(name(), prop::collection::vec(name(), 0..3), prop::collection::vec(body_item, 0..3))
+		.prop_map(|(n, params, body)| FunctionDecl {
+			name: identifier(&n),
+			params: params.iter().map(|p| identifier(p)).collect(),
+			body,
+		})
+}
+
+fn simple_statement() -> impl Strategy<Value = Statement> {
+	prop_oneof![
+		expression().prop_map(Statement::ExprStmt),
+		expression().prop_map(|e| Statement::PrintStmt(token(TokenType::Print, "print"), e)),
+		expression().prop_map(|e| Statement::Return(token(TokenType::Return, "return"), e)),
+	]
+}
+
+// declarations only appear in blocks and at the top level, never as the
+// body of an if or while
+fn var_declaration() -> impl Strategy<Value = Statement> {
+	(name(), expression()).prop_map(|(n, e)| Statement::VarDecl(identifier(&n), e))
+}
+
+fn statement() -> impl Strategy<Value = Statement> {
+	simple_statement().prop_recursive(3, 24, 3, |inner| {
+		// an if without an else can't be the then-branch of one with an else:
+		// the else would attach to the inner if
+		let block = prop::collection::vec(prop_oneof![inner.clone(), var_declaration()], 0..3).prop_map(Statement::Block);
+		let branch = prop_oneof![block.clone(), simple_statement()];
+		prop_oneof![
+			block,
+			(expression(), branch.clone(), prop::option::of(branch.clone())).prop_map(|(c, t, e)| {
+				Statement::If(c, Box::new(t), e.map(Box::new))
+			}),
+			(expression(), inner.clone()).prop_map(|(c, body)| Statement::While(c, Box::new(body))),
+		]
+	})
+}
+
+fn declaration() -> impl Strategy<Value = Statement> {
+	prop_oneof![
+		4 => statement(),
+		1 => var_declaration(),
+		1 => function().prop_map(|f| Statement::Function(Rc::new(f))),
+		1 => (name(), prop::option::of(name()), prop::collection::vec(function(), 0..3)).prop_map(|(n, superclass, methods)| {
+			// a class can't inherit from itself
+			let superclass = superclass.filter(|s| *s != n).map(|s| Expression::Variable(identifier(&s), None));
+			Statement::Class(identifier(&n), superclass, methods.into_iter().map(Rc::new).collect())
+		}),
+	]
+}
+
+fn parse(source: &str) -> Vec<Statement> {
+	let tokens = Scanner::new(source, &Interner::new()).scan_tokens().unwrap_or_else(|e| panic!("{:?} scanning:\n{}", e, source));
+	Parser::new(tokens).parse().unwrap_or_else(|e| panic!("{:?} parsing:\n{}", e, source))
+}
+
+proptest! {
+	// failing cases are reported with the source, not saved to disk
+	#![proptest_config(ProptestConfig { failure_persistence: None, ..ProptestConfig::default() })]
+
+	#[test]
+	fn printed_expressions_parse_to_the_same_tree(expr in expression()) {
+		let source = printer::print_expression(&expr) + ";";
+		let program = parse(&source);
+		prop_assert_eq!(program.len(), 1);
+		match program[0] {
+			Statement::ExprStmt(ref parsed) => prop_assert_eq!(printer::lisp_expression(parsed), printer::lisp_expression(&expr)),
+			_ => prop_assert!(false, "not an expression statement: {}", source),
+		}
+	}
+
+	#[test]
+	fn printed_programs_parse_to_the_same_tree(program in prop::collection::vec(declaration(), 0..4)) {
+		let source = printer::print_program(&program);
+		let parsed = parse(&source);
+		prop_assert_eq!(printer::lisp_program(&parsed), printer::lisp_program(&program));
+		prop_assert_eq!(printer::print_program(&parsed), source);
+	}
+}
@@ -0,0 +1,95 @@
+// Runs every program in tests/lox on both backends and checks what it
+// prints. A program states what it expects in comments: each
+// `// expect: text` is a line of output, and each `// expect error: text`
+// a line of the errors reported, in order. Both backends must match both
+// lists exactly, with and without the optimizer, and the VM must also do so
+// when collecting garbage before every allocation.
+
+extern crate rustlox;
+
+mod common;
+
+use common::Captured;
+use rustlox::{parse_program, Interner, Lox, Optimizer, Vm};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+struct Outcome {
+	output: Vec<String>,
+	errors: Vec<String>,
+}
+
+fn lines(text: &str) -> Vec<String> {
+	text.lines().map(String::from).collect()
+}
+
+fn error_lines(errors: Vec<rustlox::LoxError>) -> Vec<String> {
+	errors.iter().flat_map(|e| e.to_string().lines().map(String::from).collect::<Vec<_>>()).collect()
+}
+
+fn expected(source: &str) -> Outcome {
+	let mut outcome = Outcome { output: vec![], errors: vec![] };
+	for line in source.lines() {
+		if let Some(ix) = line.find("// expect error: ") {
+			outcome.errors.push(line[ix + "// expect error: ".len()..].to_string());
+		} else if let Some(ix) = line.find("// expect: ") {
+			outcome.output.push(line[ix + "// expect: ".len()..].to_string());
+		}
+	}
+	outcome
+}
+
+fn run_tree_walker(source: &str, optimize: bool) -> Outcome {
+	let captured = Captured::default();
+	let mut lox = Lox::with_output(Box::new(captured.clone()));
+	lox.set_optimize(optimize);
+	let errors = lox.run(source).err().unwrap_or_default();
+	Outcome { output: lines(&captured.text()), errors: error_lines(errors) }
+}
+
+fn run_vm(source: &str, optimize: bool, gc_stress: bool) -> Outcome {
+	let captured = Captured::default();
+	let mut vm = Vm::with_output(Box::new(captured.clone()));
+	vm.set_gc_stress(gc_stress);
+	let interner = Interner::new();
+	let result = parse_program(source, &interner).and_then(|mut program| {
+		if optimize {
+			Optimizer::new(&interner).optimize(&mut program);
+		}
+		vm.interpret(&program).map_err(|e| vec![e])
+	});
+	Outcome { output: lines(&captured.text()), errors: error_lines(result.err().unwrap_or_default()) }
+}
+
+fn programs() -> Vec<PathBuf> {
+	let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("lox");
+	let mut paths: Vec<PathBuf> = fs::read_dir(dir).unwrap()
+		.map(|entry| entry.unwrap().path())
+		.filter(|path| path.extension().is_some_and(|e| e == "lox"))
+		.collect();
+	paths.sort();
+	paths
+}
+
+#[test]
+fn programs_print_what_they_expect_on_both_backends() {
+	let mut failures = vec![];
+	for path in programs() {
+		let source = fs::read_to_string(&path).unwrap();
+		let expected = expected(&source);
+		let runs = vec![
+			("tree-walker", run_tree_walker(&source, false)),
+			("optimized tree-walker", run_tree_walker(&source, true)),
+			("vm", run_vm(&source, false, false)),
+			("optimized vm", run_vm(&source, true, false)),
+			("vm under gc stress", run_vm(&source, false, true)),
+		];
+		for (backend, outcome) in runs {
+			if outcome != expected {
+				failures.push(format!("{} on {}:\nexpected {:#?}\n     got {:#?}", path.display(), backend, expected, outcome));
+			}
+		}
+	}
+	assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
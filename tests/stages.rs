@@ -0,0 +1,51 @@
+// Drives the scanner, parser, resolver and interpreter separately, the
+// way a host that doesn't go through `Lox` would.
+
+extern crate rustlox;
+
+use rustlox::{parse_program, Interner, Interpreter, Optimizer, Parser, Resolver, Scanner, Statement, Value};
+
+fn parse(source: &str, interner: &Interner) -> Vec<Statement> {
+	let tokens = Scanner::new(source, interner).scan_tokens().unwrap();
+	Parser::new(tokens).parse().unwrap()
+}
+
+#[test]
+fn a_copy_of_a_program_can_be_resolved() {
+	let mut program = parse("fun f(a) { { var b = a; return b; } } class C { m() { return this; } }", &Interner::new());
+	let mut copy = program.clone();
+	Resolver::new().resolve(&mut copy).unwrap();
+	Resolver::new().resolve(&mut program).unwrap();
+}
+
+#[test]
+fn a_program_can_be_optimized_after_its_functions_are_made() {
+	let mut interpreter = Interpreter::new();
+	let mut program = parse_program("fun f() { return 1 + 2; } f();", interpreter.interner()).unwrap();
+	assert_eq!(interpreter.interpret(&program).unwrap(), Value::Number(3.0));
+	Optimizer::new(interpreter.interner()).optimize(&mut program);
+	assert_eq!(interpreter.interpret(&program).unwrap(), Value::Number(3.0));
+}
+
+#[test]
+fn names_scanned_with_the_interpreters_table_are_found() {
+	let mut interpreter = Interpreter::new();
+	let mut program = parse("class A { init() { this.x = 1; } } A().x + clock() * 0;", interpreter.interner());
+	Resolver::new().resolve(&mut program).unwrap();
+	assert_eq!(interpreter.interpret(&program).unwrap(), Value::Number(1.0));
+}
+
+#[test]
+fn the_resolver_reports_every_error() {
+	let mut program = parse("return 1; { var a = 1; var a = 2; } fun f() { this; }", &Interner::new());
+	let errors = Resolver::new().resolve(&mut program).unwrap_err();
+	assert_eq!(errors.len(), 3);
+}
+
+#[test]
+fn a_program_can_be_run_more_than_once() {
+	let mut interpreter = Interpreter::new();
+	let program = parse_program("var n = 0; for (var i = 0; i < 100; i = i + 1) { n = n + i; } n;", interpreter.interner()).unwrap();
+	assert_eq!(interpreter.interpret(&program).unwrap(), Value::Number(4950.0));
+	assert_eq!(interpreter.interpret(&program).unwrap(), Value::Number(4950.0));
+}
@@ -0,0 +1,42 @@
+// The VM kept and reused across programs, as the REPL and embedders do.
+
+extern crate rustlox;
+
+mod common;
+
+use common::{Captured, Closed};
+use rustlox::{parse_program, Interner, Vm};
+
+fn run(vm: &mut Vm, names: &Interner, source: &str) -> bool {
+	let program = parse_program(source, names).unwrap();
+	vm.interpret(&program).is_ok()
+}
+
+#[test]
+fn closures_keep_their_variables_after_a_runtime_error() {
+	for &gc_stress in &[false, true] {
+		let captured = Captured::default();
+		let mut vm = Vm::with_output(Box::new(captured.clone()));
+		vm.set_gc_stress(gc_stress);
+		let names = Interner::new();
+		// the locals before x put its slot past the end of the next run's stack
+		assert!(!run(&mut vm, &names, "var f; { var a = 1; var b = 2; var x = \"captured\"; fun g() { return x; } f = g; nil(); }"));
+		assert!(run(&mut vm, &names, "print f();"));
+		assert_eq!(captured.text(), "captured\n");
+	}
+}
+
+#[test]
+fn a_failed_write_is_a_runtime_error() {
+	let names = Interner::new();
+	let program = parse_program("var a = 1;\nprint a;", &names).unwrap();
+	let mut vm = Vm::with_output(Box::new(Closed));
+	let error = vm.interpret(&program).unwrap_err();
+	assert_eq!(error.to_string(), "[line 2:1] Runtime error at 'print': Could not write output: broken pipe.\n[line 2] in script");
+
+	// the listings write to the same sink
+	vm.set_trace(true);
+	assert_eq!(vm.interpret(&program).unwrap_err().message, "Could not write output: broken pipe.");
+	vm.set_disassemble(true);
+	assert_eq!(vm.interpret(&program).unwrap_err().message, "Could not write output: broken pipe.");
+}
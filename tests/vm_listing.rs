@@ -0,0 +1,61 @@
+// What the VM writes to its output when asked to show its bytecode.
+
+extern crate rustlox;
+
+mod common;
+
+use common::Captured;
+use rustlox::{parse_program, Interner, Vm};
+
+fn run(source: &str, disassemble: bool, trace: bool) -> String {
+	let captured = Captured::default();
+	let mut vm = Vm::with_output(Box::new(captured.clone()));
+	vm.set_disassemble(disassemble);
+	vm.set_trace(trace);
+	let program = parse_program(source, &Interner::new()).unwrap();
+	vm.interpret(&program).unwrap();
+	captured.text()
+}
+
+#[test]
+fn every_function_is_listed_before_the_program_runs() {
+	let listing = "\
+== <script> ==
+0000    1 OP_CLOSURE          1 <fn twice>
+0003    | OP_DEFINE_GLOBAL    0 'twice'
+0006    2 OP_GET_GLOBAL       0 'twice'
+0009    | OP_CONSTANT         2 '4'
+0012    | OP_CALL             1
+0014    | OP_PRINT
+0015    | OP_NIL
+0016    | OP_RETURN
+== <fn twice> ==
+0000    1 OP_GET_LOCAL        1
+0002    | OP_CONSTANT         0 '2'
+0005    | OP_MULTIPLY
+0006    | OP_RETURN
+0007    | OP_NIL
+0008    | OP_RETURN
+8
+";
+	assert_eq!(run("fun twice(x) { return x * 2; }\nprint twice(4);", true, false), listing);
+}
+
+#[test]
+fn a_trace_shows_the_stack_before_each_instruction() {
+	let trace = "          [ <script> ]
+0000    1 OP_CONSTANT         0 '1'
+          [ <script> ][ 1 ]
+0003    | OP_CONSTANT         1 '2'
+          [ <script> ][ 1 ][ 2 ]
+0006    | OP_ADD
+          [ <script> ][ 3 ]
+0007    | OP_PRINT
+3
+          [ <script> ]
+0008    | OP_NIL
+          [ <script> ][ nil ]
+0009    | OP_RETURN
+";
+	assert_eq!(run("print 1 + 2;", false, true), trace);
+}